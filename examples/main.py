@@ -83,7 +83,7 @@ def index(app_data):
 
 class AppData:
     def __init__(self):
-        self.conn = sqlite3.connect("database.db")
+        self.conn = sqlite3.connect("database.db", check_same_thread=False)
         self.n = 0
         self.tera = templating.Tera("./templates/**/*.html")
 
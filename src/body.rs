@@ -0,0 +1,177 @@
+use std::sync::{Arc, Mutex};
+
+use http_body_util::BodyExt;
+use hyper::body::{Bytes, Incoming};
+use pyo3::{
+    exceptions::{PyIOError, PyStopIteration},
+    ffi::c_str,
+    prelude::*,
+    sync::GILOnceCell,
+    types::PyBytes,
+};
+use tokio::{io::AsyncReadExt, sync::mpsc};
+
+use crate::decompression::Reader;
+
+static HELPERS: GILOnceCell<Py<PyModule>> = GILOnceCell::new();
+
+/// How many chunks may wait for the handler before reading from the client pauses.
+const BUFFERED_CHUNKS: usize = 4;
+
+const DECODED_CHUNK_SIZE: usize = 64 * 1024;
+
+type Chunk = Result<Bytes, String>;
+
+/// The body of a streaming route, handed to the handler as it arrives.
+///
+/// Iterate it with `for` in a regular handler or `async for` in an `async def`
+/// one. Reading from the client only continues as chunks are consumed.
+#[derive(Clone, Debug)]
+#[pyclass]
+pub struct RequestBody {
+    receiver: Arc<Mutex<mpsc::Receiver<Chunk>>>,
+}
+
+impl RequestBody {
+    /// Starts forwarding `body` chunk by chunk, failing once more than
+    /// `max_body_size` bytes have been received.
+    pub fn spawn(body: Incoming, max_body_size: Option<usize>) -> Self {
+        let (sender, receiver) = mpsc::channel(BUFFERED_CHUNKS);
+        tokio::spawn(forward(body, max_body_size, sender));
+        Self {
+            receiver: Arc::new(Mutex::new(receiver)),
+        }
+    }
+
+    /// Like [`RequestBody::spawn`] for a body that is decoded as it arrives;
+    /// the limit applies to the decoded bytes.
+    pub fn spawn_decoded(reader: Reader, max_body_size: Option<usize>) -> Self {
+        let (sender, receiver) = mpsc::channel(BUFFERED_CHUNKS);
+        tokio::spawn(forward_decoded(reader, max_body_size, sender));
+        Self {
+            receiver: Arc::new(Mutex::new(receiver)),
+        }
+    }
+}
+
+async fn forward_decoded(
+    mut reader: Reader,
+    max_body_size: Option<usize>,
+    sender: mpsc::Sender<Chunk>,
+) {
+    let mut received = 0;
+    loop {
+        let mut chunk = Vec::with_capacity(DECODED_CHUNK_SIZE);
+        let chunk = match reader.read_buf(&mut chunk).await {
+            Ok(0) => return,
+            Ok(_) => Bytes::from(chunk),
+            Err(err) => {
+                _ = sender.send(Err(err.to_string())).await;
+                return;
+            }
+        };
+        received += chunk.len();
+        if max_body_size.is_some_and(|limit| received > limit) {
+            _ = sender
+                .send(Err("decompressed request body is too large".into()))
+                .await;
+            return;
+        }
+        if sender.send(Ok(chunk)).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn forward(mut body: Incoming, max_body_size: Option<usize>, sender: mpsc::Sender<Chunk>) {
+    let mut received = 0;
+    while let Some(frame) = body.frame().await {
+        let chunk = match frame {
+            Ok(frame) => match frame.into_data() {
+                Ok(data) => data,
+                Err(_) => continue,
+            },
+            Err(err) => {
+                _ = sender.send(Err(err.to_string())).await;
+                return;
+            }
+        };
+        received += chunk.len();
+        if max_body_size.is_some_and(|limit| received > limit) {
+            _ = sender
+                .send(Err("request body exceeds max_body_size".into()))
+                .await;
+            return;
+        }
+        if sender.send(Ok(chunk)).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[pymethods]
+impl RequestBody {
+    /// The next chunk, or `None` once the body is complete. Blocks without
+    /// holding the GIL until the chunk arrives.
+    fn read_chunk<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let receiver = self.receiver.clone();
+        let chunk = py.allow_threads(move || receiver.lock().unwrap().blocking_recv());
+        match chunk {
+            Some(Ok(chunk)) => Ok(Some(PyBytes::new(py, &chunk))),
+            Some(Err(err)) => Err(PyIOError::new_err(err)),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the rest of the body into memory.
+    fn read<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let mut data = Vec::new();
+        while let Some(chunk) = self.read_chunk(py)? {
+            data.extend_from_slice(chunk.as_bytes());
+        }
+        Ok(PyBytes::new(py, &data))
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        self.read_chunk(py)?
+            .ok_or_else(|| PyStopIteration::new_err(()))
+    }
+
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Waits for the next chunk in the default executor so the event loop
+    /// keeps running.
+    fn __anext__<'py>(slf: Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        helpers(slf.py())?.call_method1("next_chunk", (slf,))
+    }
+}
+
+fn helpers(py: Python<'_>) -> PyResult<&Bound<'_, PyModule>> {
+    let module = HELPERS.get_or_try_init(py, || {
+        PyModule::from_code(
+            py,
+            c_str!(
+                r#"
+import asyncio
+
+
+async def next_chunk(body):
+    chunk = await asyncio.get_running_loop().run_in_executor(None, body.read_chunk)
+    if chunk is None:
+        raise StopAsyncIteration
+    return chunk
+"#
+            ),
+            c_str!("oxapy_body.py"),
+            c_str!("oxapy_body"),
+        )
+        .map(Into::into)
+    })?;
+    Ok(module.bind(py))
+}
@@ -0,0 +1,305 @@
+use std::io::{self, Write};
+
+use flate2::write::GzEncoder;
+use hyper::{
+    body::{Body, Bytes},
+    header::{self, HeaderMap, HeaderValue},
+    Response as HyperResponse,
+};
+use tokio::{sync::mpsc, task::spawn_blocking};
+
+use crate::streaming::ResponseBody;
+
+/// Content types worth compressing; everything else, images, archives and
+/// other already-compressed media included, is sent as is.
+const COMPRESSIBLE: &[&str] = &[
+    "application/javascript",
+    "application/json",
+    "application/wasm",
+    "application/x-javascript",
+    "application/x-www-form-urlencoded",
+    "application/xml",
+    "image/svg+xml",
+    "image/x-icon",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    /// In order of preference when the client accepts several equally.
+    const ALL: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+/// Compresses response bodies for clients that accept it.
+#[derive(Clone, Copy, Debug)]
+pub struct Compression {
+    /// Bodies of a known size below this are sent uncompressed.
+    pub min_size: usize,
+}
+
+impl Compression {
+    /// Compresses `response` with the best encoding `request` accepts, when its
+    /// content type and size make that worthwhile.
+    pub async fn apply(
+        &self,
+        mut response: HyperResponse<ResponseBody>,
+        request: &HeaderMap,
+    ) -> HyperResponse<ResponseBody> {
+        if !self.eligible(&response) {
+            return response;
+        }
+        let headers = response.headers_mut();
+        headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        let Some(encoding) = negotiate(request) else {
+            return response;
+        };
+
+        let (mut parts, body) = response.into_parts();
+        // Compressing takes a while for large bodies, so it happens off the
+        // threads that drive connections.
+        let body = match body {
+            ResponseBody::Full(data) => {
+                let input = data.clone();
+                match spawn_blocking(move || compress(encoding, &input)).await {
+                    Ok(Ok(compressed)) => ResponseBody::Full(compressed),
+                    _ => return HyperResponse::from_parts(parts, ResponseBody::Full(data)),
+                }
+            }
+            ResponseBody::Stream(receiver) => {
+                let Ok(encoder) = Encoder::new(encoding) else {
+                    return HyperResponse::from_parts(parts, ResponseBody::Stream(receiver));
+                };
+                let (sender, compressed) = mpsc::channel(1);
+                tokio::spawn(compress_stream(encoder, receiver, sender));
+                ResponseBody::Stream(compressed)
+            }
+        };
+
+        let headers = &mut parts.headers;
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.name()),
+        );
+        headers.remove(header::CONTENT_LENGTH);
+        headers.remove(header::ACCEPT_RANGES);
+        // The compressed bytes differ from the original, so a strong validator
+        // no longer applies.
+        if let Some(etag) = headers
+            .get(header::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+        {
+            if !etag.starts_with("W/") {
+                if let Ok(weak) = HeaderValue::try_from(format!("W/{etag}")) {
+                    headers.insert(header::ETAG, weak);
+                }
+            }
+        }
+        HyperResponse::from_parts(parts, body)
+    }
+
+    fn eligible(&self, response: &HyperResponse<ResponseBody>) -> bool {
+        let status = response.status();
+        let headers = response.headers();
+        if status.is_informational()
+            || matches!(status.as_u16(), 204 | 206 | 304)
+            || headers.contains_key(header::CONTENT_ENCODING)
+            || headers.contains_key(header::CONTENT_RANGE)
+        {
+            return false;
+        }
+        let len = response.body().size_hint().exact().or_else(|| {
+            let len = headers.get(header::CONTENT_LENGTH)?;
+            len.to_str().ok()?.parse().ok()
+        });
+        if len.is_some_and(|len| len < self.min_size as u64) {
+            return false;
+        }
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(compressible)
+    }
+}
+
+fn compressible(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    // Event streams are left alone: proxies and clients expect each event to
+    // arrive as soon as it is sent.
+    if mime == "text/event-stream" {
+        return false;
+    }
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || COMPRESSIBLE.contains(&mime.as_str())
+}
+
+/// Picks the encoding with the highest `q` in `Accept-Encoding`; `*` stands for
+/// any encoding not listed and `q=0` refuses one.
+fn negotiate(request: &HeaderMap) -> Option<Encoding> {
+    let mut weights = [None; Encoding::ALL.len()];
+    let mut wildcard = None;
+    let values = request.get_all(header::ACCEPT_ENCODING).iter();
+    for item in values
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|v| v.split(','))
+    {
+        let mut params = item.split(';');
+        let name = params
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let q = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match name.as_str() {
+            "*" => wildcard = Some(q),
+            "br" => weights[0] = Some(q),
+            "zstd" => weights[1] = Some(q),
+            "gzip" | "x-gzip" => weights[2] = Some(q),
+            _ => {}
+        }
+    }
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for (encoding, weight) in Encoding::ALL.into_iter().zip(weights) {
+        let q = weight.or(wildcard).unwrap_or(0.0);
+        if q > 0.0 && best.is_none_or(|(_, best)| q > best) {
+            best = Some((encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+enum Encoder {
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+    Gzip(GzEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(encoding: Encoding) -> io::Result<Self> {
+        Ok(match encoding {
+            // Brotli's default quality is too slow for responses built on the fly.
+            Encoding::Brotli => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                4096,
+                5,
+                22,
+            ))),
+            Encoding::Zstd => Encoder::Zstd(zstd::stream::write::Encoder::new(Vec::new(), 3)?),
+            Encoding::Gzip => {
+                Encoder::Gzip(GzEncoder::new(Vec::new(), flate2::Compression::default()))
+            }
+        })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Encoder::Brotli(encoder) => encoder.as_mut(),
+            Encoder::Zstd(encoder) => encoder,
+            Encoder::Gzip(encoder) => encoder,
+        }
+    }
+
+    fn output(&mut self) -> &mut Vec<u8> {
+        match self {
+            Encoder::Brotli(encoder) => encoder.get_mut(),
+            Encoder::Zstd(encoder) => encoder.get_mut(),
+            Encoder::Gzip(encoder) => encoder.get_mut(),
+        }
+    }
+
+    /// Compresses `chunk` and flushes, so everything written so far can be
+    /// decoded by the client right away.
+    fn write(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+        let writer = self.writer();
+        writer.write_all(chunk)?;
+        writer.flush()?;
+        Ok(std::mem::take(self.output()).into())
+    }
+
+    fn finish(self) -> io::Result<Bytes> {
+        let output = match self {
+            Encoder::Brotli(encoder) => encoder.into_inner(),
+            Encoder::Zstd(encoder) => encoder.finish()?,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+        };
+        Ok(output.into())
+    }
+}
+
+fn compress(encoding: Encoding, data: &[u8]) -> io::Result<Bytes> {
+    let mut encoder = Encoder::new(encoding)?;
+    encoder.writer().write_all(data)?;
+    encoder.finish()
+}
+
+/// Compresses each chunk of `receiver` on the blocking pool, handing the encoder
+/// back and forth so its state carries over between chunks.
+async fn compress_stream(
+    mut encoder: Encoder,
+    mut receiver: mpsc::Receiver<io::Result<Bytes>>,
+    sender: mpsc::Sender<io::Result<Bytes>>,
+) {
+    while let Some(chunk) = receiver.recv().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                _ = sender.send(Err(err)).await;
+                return;
+            }
+        };
+        let compressed = spawn_blocking(move || {
+            let compressed = encoder.write(&chunk);
+            (encoder, compressed)
+        })
+        .await;
+        let compressed = match compressed {
+            Ok((returned, compressed)) => {
+                encoder = returned;
+                compressed
+            }
+            Err(err) => {
+                _ = sender.send(Err(io::Error::other(err))).await;
+                return;
+            }
+        };
+        match compressed {
+            Ok(compressed) if compressed.is_empty() => continue,
+            Ok(compressed) => {
+                if sender.send(Ok(compressed)).await.is_err() {
+                    return;
+                }
+            }
+            Err(err) => {
+                _ = sender.send(Err(err)).await;
+                return;
+            }
+        }
+    }
+    let finished = spawn_blocking(move || encoder.finish()).await;
+    _ = sender
+        .send(finished.unwrap_or_else(|err| Err(io::Error::other(err))))
+        .await;
+}
@@ -0,0 +1,169 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::Notify,
+    time::Instant,
+};
+
+/// Book-keeping for a single connection, used to enforce the keep-alive idle
+/// timeout and the maximum number of requests per connection.
+pub struct ConnectionState {
+    created: Instant,
+    last_active_ms: AtomicU64,
+    in_flight: AtomicUsize,
+    served: AtomicUsize,
+    max_requests: Option<usize>,
+    exhausted: Notify,
+}
+
+impl ConnectionState {
+    pub fn new(max_requests: Option<usize>) -> Arc<Self> {
+        Arc::new(Self {
+            created: Instant::now(),
+            last_active_ms: AtomicU64::new(0),
+            in_flight: AtomicUsize::new(0),
+            served: AtomicUsize::new(0),
+            max_requests,
+            exhausted: Notify::new(),
+        })
+    }
+
+    fn touch(&self) {
+        let elapsed = self.created.elapsed().as_millis() as u64;
+        self.last_active_ms.store(elapsed, Ordering::Relaxed);
+    }
+
+    fn last_active(&self) -> Instant {
+        self.created + Duration::from_millis(self.last_active_ms.load(Ordering::Relaxed))
+    }
+
+    /// Marks a request as in flight until the returned guard is dropped.
+    pub fn request(self: &Arc<Self>) -> RequestGuard {
+        self.touch();
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let served = self.served.fetch_add(1, Ordering::SeqCst) + 1;
+        if self.max_requests.is_some_and(|max| served >= max) {
+            self.exhausted.notify_one();
+        }
+        RequestGuard(self.clone())
+    }
+
+    /// Resolves once the connection has seen `max_requests` requests.
+    pub async fn exhausted(&self) {
+        match self.max_requests {
+            Some(_) => self.exhausted.notified().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Resolves once no request is in flight and no bytes have been read or
+    /// written for `timeout`.
+    pub async fn idle(&self, timeout: Option<Duration>) {
+        let Some(timeout) = timeout else {
+            return std::future::pending().await;
+        };
+        loop {
+            if self.in_flight.load(Ordering::SeqCst) > 0 {
+                tokio::time::sleep(timeout).await;
+                continue;
+            }
+            match self.last_active().checked_add(timeout) {
+                Some(deadline) if deadline <= Instant::now() => return,
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                // Too far off to represent, so the connection never counts as idle.
+                None => return std::future::pending().await,
+            }
+        }
+    }
+}
+
+pub struct RequestGuard(Arc<ConnectionState>);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        self.0.touch();
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Wraps a connection's I/O and records the time of the last read or write.
+pub struct TrackedIo<S> {
+    inner: S,
+    state: Arc<ConnectionState>,
+}
+
+impl<S> TrackedIo<S> {
+    pub fn new(inner: S, state: Arc<ConnectionState>) -> Self {
+        Self { inner, state }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for TrackedIo<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if buf.filled().len() > filled {
+            this.state.touch();
+        }
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for TrackedIo<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            if n > 0 {
+                this.state.touch();
+            }
+        }
+        poll
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(n)) = poll {
+            if n > 0 {
+                this.state.touch();
+            }
+        }
+        poll
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyString};
+
+// Seconds from the Unix epoch to the year 10000, which an HTTP date cannot express.
+const MAX_HTTP_DATE: u64 = 253_402_300_800;
+
+/// Parses the `Cookie` request headers. When a name appears more than once the
+/// first value wins, since browsers send the most specific cookie first.
+pub fn parse<'a>(headers: impl IntoIterator<Item = &'a str>) -> HashMap<String, String> {
+    let mut cookies = HashMap::new();
+    for pair in headers.into_iter().flat_map(|header| header.split(';')) {
+        let Some((name, value)) = pair.split_once('=') else {
+            continue;
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
+        }
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        cookies
+            .entry(name.to_string())
+            .or_insert_with(|| value.to_string());
+    }
+    cookies
+}
+
+/// The attributes of a `Set-Cookie` header.
+#[derive(Default)]
+pub struct Cookie<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+    pub max_age: Option<i64>,
+    pub expires: Option<String>,
+    pub path: Option<&'a str>,
+    pub domain: Option<&'a str>,
+    pub secure: bool,
+    pub httponly: bool,
+    pub samesite: Option<&'a str>,
+}
+
+impl Cookie<'_> {
+    /// Renders the cookie as a `Set-Cookie` header value.
+    pub fn to_header(&self) -> PyResult<String> {
+        if self.name.is_empty() || !self.name.bytes().all(is_token) {
+            return Err(PyValueError::new_err(format!(
+                "Invalid cookie name: {:?}",
+                self.name
+            )));
+        }
+        if !self.value.bytes().all(is_cookie_octet) {
+            return Err(PyValueError::new_err(format!(
+                "Invalid value for cookie {}; encode it first",
+                self.name
+            )));
+        }
+        let attributes = [self.path, self.domain, self.expires.as_deref()];
+        for attribute in attributes.into_iter().flatten() {
+            if attribute.contains(';') || attribute.chars().any(char::is_control) {
+                return Err(PyValueError::new_err(format!(
+                    "Invalid cookie attribute: {attribute:?}"
+                )));
+            }
+        }
+
+        let mut header = format!("{}={}", self.name, self.value);
+        if let Some(max_age) = self.max_age {
+            header.push_str(&format!("; Max-Age={max_age}"));
+        }
+        if let Some(expires) = &self.expires {
+            header.push_str(&format!("; Expires={expires}"));
+        }
+        if let Some(domain) = self.domain {
+            header.push_str(&format!("; Domain={domain}"));
+        }
+        if let Some(path) = self.path {
+            header.push_str(&format!("; Path={path}"));
+        }
+        if self.secure {
+            header.push_str("; Secure");
+        }
+        if self.httponly {
+            header.push_str("; HttpOnly");
+        }
+        if let Some(samesite) = self.samesite {
+            let samesite = match samesite.to_ascii_lowercase().as_str() {
+                "strict" => "Strict",
+                "lax" => "Lax",
+                "none" => "None",
+                _ => {
+                    return Err(PyValueError::new_err(
+                        "samesite must be 'strict', 'lax' or 'none'",
+                    ))
+                }
+            };
+            header.push_str(&format!("; SameSite={samesite}"));
+        }
+        Ok(header)
+    }
+}
+
+/// Formats `expires` as an HTTP date. Accepts a `datetime`, a Unix timestamp
+/// or a string that is used verbatim.
+pub fn expires(expires: &Bound<'_, PyAny>) -> PyResult<String> {
+    if let Ok(text) = expires.downcast::<PyString>() {
+        return Ok(text.to_string());
+    }
+    let timestamp: f64 = match expires.hasattr("timestamp")? {
+        true => expires.call_method0("timestamp")?.extract()?,
+        false => expires.extract()?,
+    };
+    if timestamp < 0.0 {
+        return Ok(epoch());
+    }
+    Duration::try_from_secs_f64(timestamp)
+        .ok()
+        .filter(|offset| offset.as_secs() < MAX_HTTP_DATE)
+        .and_then(|offset| UNIX_EPOCH.checked_add(offset))
+        .map(httpdate::fmt_http_date)
+        .ok_or_else(|| PyValueError::new_err(format!("Invalid cookie expiry: {timestamp}")))
+}
+
+pub fn epoch() -> String {
+    httpdate::fmt_http_date(UNIX_EPOCH)
+}
+
+fn is_token(byte: u8) -> bool {
+    byte.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&byte)
+}
+
+// RFC 6265: printable ASCII except whitespace, DQUOTE, comma, semicolon and backslash.
+fn is_cookie_octet(byte: u8) -> bool {
+    byte.is_ascii_graphic() && !b"\",;\\".contains(&byte)
+}
@@ -0,0 +1,116 @@
+use std::sync::Mutex;
+
+use pyo3::{
+    ffi::c_str,
+    prelude::*,
+    sync::GILOnceCell,
+    types::{PyCFunction, PyDict, PyTuple},
+};
+use tokio::sync::oneshot;
+
+static HELPERS: GILOnceCell<Py<PyModule>> = GILOnceCell::new();
+static EVENT_LOOP: GILOnceCell<Py<PyAny>> = GILOnceCell::new();
+
+pub type Pending = oneshot::Receiver<PyResult<Py<PyAny>>>;
+
+fn helpers(py: Python<'_>) -> PyResult<&Bound<'_, PyModule>> {
+    let module = HELPERS.get_or_try_init(py, || {
+        PyModule::from_code(
+            py,
+            c_str!(
+                r#"
+import asyncio
+import inspect
+import threading
+
+
+async def drive(awaitable):
+    result = await awaitable
+    while inspect.isawaitable(result):
+        result = await result
+    return result
+
+
+async def resolved(value):
+    return value
+
+
+def awaitable_next(next_fn):
+    def wrapper(**kwargs):
+        result = next_fn(**kwargs)
+        return result if inspect.isawaitable(result) else resolved(result)
+    return wrapper
+
+
+def start_loop():
+    loop = asyncio.new_event_loop()
+    threading.Thread(target=loop.run_forever, name="oxapy-asyncio", daemon=True).start()
+    return loop
+"#
+            ),
+            c_str!("oxapy_coroutine.py"),
+            c_str!("oxapy_coroutine"),
+        )
+        .map(Into::into)
+    })?;
+    Ok(module.bind(py))
+}
+
+fn event_loop(py: Python<'_>) -> PyResult<&Bound<'_, PyAny>> {
+    let event_loop = EVENT_LOOP.get_or_try_init(py, || {
+        helpers(py)?.call_method0("start_loop").map(Into::into)
+    })?;
+    Ok(event_loop.bind(py))
+}
+
+pub fn is_awaitable(obj: &Bound<'_, PyAny>) -> PyResult<bool> {
+    let inspect = obj.py().import("inspect")?;
+    inspect.call_method1("isawaitable", (obj,))?.extract()
+}
+
+pub fn is_coroutine_function(obj: &Bound<'_, PyAny>) -> PyResult<bool> {
+    let inspect = obj.py().import("inspect")?;
+    inspect
+        .call_method1("iscoroutinefunction", (obj,))?
+        .extract()
+}
+
+/// Wraps `next` so an async middleware can always `await` it, even when the
+/// rest of the chain is synchronous.
+pub fn awaitable_next(py: Python<'_>, next: Py<PyAny>) -> PyResult<Py<PyAny>> {
+    Ok(helpers(py)?.call_method1("awaitable_next", (next,))?.into())
+}
+
+/// Schedules `awaitable` on the shared asyncio loop, which runs on its own
+/// thread, and returns a receiver that resolves once it has completed.
+pub fn spawn(awaitable: &Bound<'_, PyAny>) -> PyResult<Pending> {
+    spawn_cancellable(awaitable).map(|(pending, _)| pending)
+}
+
+/// Like [`spawn`], but also returns the `concurrent.futures.Future` of the
+/// task; calling its `cancel()` raises `CancelledError` inside the coroutine.
+pub fn spawn_cancellable(awaitable: &Bound<'_, PyAny>) -> PyResult<(Pending, Py<PyAny>)> {
+    let py = awaitable.py();
+    let (sender, receiver) = oneshot::channel();
+    let sender = Mutex::new(Some(sender));
+
+    let coroutine = helpers(py)?.call_method1("drive", (awaitable,))?;
+    let asyncio = py.import("asyncio")?;
+    let future = asyncio.call_method1("run_coroutine_threadsafe", (coroutine, event_loop(py)?))?;
+
+    let on_done = PyCFunction::new_closure(
+        py,
+        None,
+        None,
+        move |args: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| -> PyResult<()> {
+            let result = args.get_item(0)?.call_method0("result").map(Into::into);
+            if let Some(sender) = sender.lock().unwrap().take() {
+                _ = sender.send(result);
+            }
+            Ok(())
+        },
+    )?;
+    future.call_method1("add_done_callback", (on_done,))?;
+
+    Ok((receiver, future.unbind()))
+}
@@ -62,43 +62,28 @@ impl Cors {
 
 impl IntoResponse for Cors {
     fn into_response(&self) -> PyResult<Response> {
-        let mut response = Status::NO_CONTENT.into_response()?;
-        self.apply_headers(&mut response);
+        let response = Status::NO_CONTENT.into_response()?;
+        self.apply_headers(&response)?;
         Ok(response)
     }
 }
 
 impl Cors {
     // Centralized method to apply CORS headers to any response
-    pub fn apply_headers(&self, response: &mut Response) {
-        response.header(
-            "Access-Control-Allow-Origin".to_string(),
-            self.origins.join(", "),
-        );
-        response.header(
-            "Access-Control-Allow-Methods".to_string(),
-            self.methods.join(", "),
-        );
-        response.header(
-            "Access-Control-Allow-Headers".to_string(),
-            self.headers.join(", "),
-        );
+    pub fn apply_headers(&self, response: &Response) -> PyResult<()> {
+        response.header("Access-Control-Allow-Origin", &self.origins.join(", "))?;
+        response.header("Access-Control-Allow-Methods", &self.methods.join(", "))?;
+        response.header("Access-Control-Allow-Headers", &self.headers.join(", "))?;
 
         if self.allow_credentials {
-            response.header(
-                "Access-Control-Allow-Credentials".to_string(),
-                "true".to_string(),
-            );
+            response.header("Access-Control-Allow-Credentials", "true")?;
         }
 
-        response.header(
-            "Access-Control-Max-Age".to_string(),
-            self.max_age.to_string(),
-        );
+        response.header("Access-Control-Max-Age", &self.max_age.to_string())
     }
 
-    pub fn apply_to_response(&self, mut response: Response) -> PyResult<Response> {
-        self.apply_headers(&mut response);
+    pub fn apply_to_response(&self, response: Response) -> PyResult<Response> {
+        self.apply_headers(&response)?;
         Ok(response)
     }
 }
@@ -0,0 +1,93 @@
+use std::{io, pin::Pin};
+
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder, ZstdDecoder};
+use futures_util::TryStreamExt;
+use http_body_util::BodyStream;
+use hyper::{
+    body::Incoming,
+    header::{self, HeaderMap},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+use tokio_util::io::StreamReader;
+
+/// The encodings a request body may arrive in, as listed in `Accept-Encoding`
+/// when one is refused.
+pub const SUPPORTED: &str = "gzip, deflate, br, zstd";
+
+pub type Reader = Pin<Box<dyn AsyncRead + Send>>;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ContentEncoding {
+    Gzip,
+    Deflate,
+    Brotli,
+    Zstd,
+}
+
+/// A `Content-Encoding` the server cannot undo.
+#[derive(Debug)]
+pub struct Unsupported;
+
+/// The encodings applied to the request body, in the order they were applied.
+pub fn encodings(headers: &HeaderMap) -> Result<Vec<ContentEncoding>, Unsupported> {
+    let mut encodings = Vec::new();
+    for value in headers.get_all(header::CONTENT_ENCODING) {
+        let value = value.to_str().map_err(|_| Unsupported)?;
+        for name in value
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let encoding = match name.to_ascii_lowercase().as_str() {
+                "identity" => continue,
+                "gzip" | "x-gzip" => ContentEncoding::Gzip,
+                "deflate" => ContentEncoding::Deflate,
+                "br" => ContentEncoding::Brotli,
+                "zstd" => ContentEncoding::Zstd,
+                _ => return Err(Unsupported),
+            };
+            encodings.push(encoding);
+        }
+    }
+    Ok(encodings)
+}
+
+/// Wraps `body` in decoders that undo `encodings`, last applied first.
+pub fn decode(body: Incoming, encodings: &[ContentEncoding]) -> Reader {
+    let data = BodyStream::new(body)
+        .map_ok(|frame| frame.into_data().unwrap_or_default())
+        .map_err(io::Error::other);
+    let mut reader: Reader = Box::pin(StreamReader::new(data));
+    for encoding in encodings.iter().rev() {
+        let buffered = BufReader::new(reader);
+        reader = match encoding {
+            ContentEncoding::Gzip => Box::pin(GzipDecoder::new(buffered)),
+            ContentEncoding::Deflate => Box::pin(ZlibDecoder::new(buffered)),
+            ContentEncoding::Brotli => Box::pin(BrotliDecoder::new(buffered)),
+            ContentEncoding::Zstd => Box::pin(ZstdDecoder::new(buffered)),
+        };
+    }
+    reader
+}
+
+/// Reads the decoded body into memory. `Ok(None)` means it decoded to more
+/// than `limit` bytes; reading stops there, so a small compressed body cannot
+/// expand without bound.
+pub async fn read(mut reader: Reader, limit: Option<usize>) -> io::Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    match limit {
+        Some(limit) => {
+            (&mut reader)
+                .take(limit as u64 + 1)
+                .read_to_end(&mut data)
+                .await?;
+            if data.len() > limit {
+                return Ok(None);
+            }
+        }
+        None => {
+            reader.read_to_end(&mut data).await?;
+        }
+    }
+    Ok(Some(data))
+}
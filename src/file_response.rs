@@ -0,0 +1,495 @@
+use std::{
+    io::SeekFrom,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hyper::{
+    body::Bytes,
+    header::{self, HeaderMap, HeaderValue},
+    Method, Response as HyperResponse,
+};
+use pyo3::{exceptions::PyIsADirectoryError, prelude::*};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+    sync::mpsc,
+};
+
+use crate::{headers::Headers, response::Response, status::Status, streaming::ResponseBody};
+
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Requests for more ranges than this get the whole file instead.
+const MAX_RANGES: usize = 32;
+
+/// A file on disk sent as a response body, read in chunks as the client
+/// consumes it.
+#[derive(Clone, Debug)]
+pub struct FileSource {
+    path: PathBuf,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+enum Piece {
+    Bytes(Bytes),
+    Range(u64, u64),
+}
+
+impl FileSource {
+    fn open(path: PathBuf) -> PyResult<Self> {
+        let metadata = std::fs::metadata(&path)?;
+        if metadata.is_dir() {
+            return Err(PyIsADirectoryError::new_err(format!(
+                "{} is a directory",
+                path.display()
+            )));
+        }
+        Ok(Self {
+            path,
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    fn etag(&self) -> String {
+        let modified = self
+            .modified
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        format!("\"{:x}-{:x}\"", modified.as_nanos(), self.len)
+    }
+
+    /// `Last-Modified` has a one second resolution, so comparisons use whole seconds.
+    fn modified_secs(&self) -> Option<u64> {
+        let since_epoch = self.modified?.duration_since(UNIX_EPOCH).ok()?;
+        Some(since_epoch.as_secs())
+    }
+
+    /// Answers the request with a `304`, the requested ranges or the whole
+    /// file. Only a `200` response is eligible for either.
+    pub fn respond(
+        &self,
+        status: u16,
+        mut headers: HeaderMap,
+        method: &Method,
+        request: &HeaderMap,
+    ) -> Result<HyperResponse<ResponseBody>, hyper::http::Error> {
+        let builder = |status: u16, headers: HeaderMap| {
+            let mut builder = HyperResponse::builder().status(status);
+            if let Some(map) = builder.headers_mut() {
+                *map = headers;
+            }
+            builder
+        };
+        let empty = || ResponseBody::Full(Bytes::new());
+        let cacheable = method == Method::GET || method == Method::HEAD;
+
+        if status == 200 && cacheable && self.not_modified(request) {
+            headers.remove(header::CONTENT_TYPE);
+            headers.remove(header::CONTENT_DISPOSITION);
+            return builder(304, headers).body(empty());
+        }
+
+        let ranges = match (status, request.get(header::RANGE)) {
+            (200, Some(range)) if method == Method::GET && self.if_range(request) => range
+                .to_str()
+                .ok()
+                .and_then(|range| parse_ranges(range, self.len)),
+            _ => None,
+        };
+
+        let (status, pieces) = match ranges {
+            None => (status, vec![Piece::Range(0, self.len)]),
+            Some(None) => {
+                let range = format!("bytes */{}", self.len);
+                headers.insert(header::CONTENT_RANGE, HeaderValue::try_from(range)?);
+                headers.insert(header::CONTENT_LENGTH, HeaderValue::from(0));
+                headers.remove(header::CONTENT_TYPE);
+                return builder(416, headers).body(empty());
+            }
+            Some(Some(ranges)) if ranges.len() == 1 => {
+                let (start, end) = ranges[0];
+                let range = format!("bytes {start}-{}/{}", end - 1, self.len);
+                headers.insert(header::CONTENT_RANGE, HeaderValue::try_from(range)?);
+                (206, vec![Piece::Range(start, end)])
+            }
+            Some(Some(ranges)) => {
+                let content_type = headers.get(header::CONTENT_TYPE).cloned();
+                let (content_type, pieces) = self.byteranges(ranges, content_type);
+                headers.insert(header::CONTENT_TYPE, HeaderValue::try_from(content_type)?);
+                (206, pieces)
+            }
+        };
+
+        let len: u64 = pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Bytes(bytes) => bytes.len() as u64,
+                Piece::Range(start, end) => end - start,
+            })
+            .sum();
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+
+        let (sender, receiver) = mpsc::channel(1);
+        tokio::spawn(send(self.path.clone(), pieces, sender));
+        builder(status, headers).body(ResponseBody::Stream(receiver))
+    }
+
+    // RFC 9110 §13.2.2: If-None-Match takes precedence over If-Modified-Since.
+    fn not_modified(&self, request: &HeaderMap) -> bool {
+        if let Some(if_none_match) = request.get(header::IF_NONE_MATCH) {
+            let etag = self.etag();
+            let if_none_match = if_none_match.to_str().unwrap_or_default();
+            return if_none_match.split(',').any(|tag| {
+                let tag = tag.trim();
+                tag == "*" || weak(tag) == weak(&etag)
+            });
+        }
+        let since = request
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| httpdate::parse_http_date(value.to_str().ok()?).ok());
+        match (since, self.modified_secs()) {
+            (Some(since), Some(modified)) => since
+                .duration_since(UNIX_EPOCH)
+                .is_ok_and(|since| modified <= since.as_secs()),
+            _ => false,
+        }
+    }
+
+    /// Whether a `Range` still applies: an `If-Range` that no longer matches
+    /// means the client's partial copy is stale and needs the whole file.
+    fn if_range(&self, request: &HeaderMap) -> bool {
+        let Some(if_range) = request.get(header::IF_RANGE) else {
+            return true;
+        };
+        let if_range = if_range.to_str().unwrap_or_default().trim();
+        if if_range.starts_with('"') {
+            return if_range == self.etag();
+        }
+        match (httpdate::parse_http_date(if_range), self.modified_secs()) {
+            (Ok(date), Some(modified)) => date
+                .duration_since(UNIX_EPOCH)
+                .is_ok_and(|date| date.as_secs() == modified),
+            _ => false,
+        }
+    }
+
+    fn byteranges(
+        &self,
+        ranges: Vec<(u64, u64)>,
+        content_type: Option<HeaderValue>,
+    ) -> (String, Vec<Piece>) {
+        let boundary = format!("oxapy-{}", self.etag().trim_matches('"'));
+        let content_type = content_type
+            .as_ref()
+            .and_then(|value| value.to_str().ok())
+            .map(|value| format!("Content-Type: {value}\r\n"))
+            .unwrap_or_default();
+
+        let mut pieces = Vec::new();
+        for (start, end) in ranges {
+            let head = format!(
+                "\r\n--{boundary}\r\n{content_type}Content-Range: bytes {start}-{}/{}\r\n\r\n",
+                end - 1,
+                self.len
+            );
+            pieces.push(Piece::Bytes(head.into()));
+            pieces.push(Piece::Range(start, end));
+        }
+        pieces.push(Piece::Bytes(format!("\r\n--{boundary}--\r\n").into()));
+        (format!("multipart/byteranges; boundary={boundary}"), pieces)
+    }
+}
+
+fn weak(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+/// Parses a `Range: bytes=...` header into sorted, non-overlapping half-open
+/// ranges; overlapping and adjacent ones are merged so no byte is sent twice.
+/// `None` means the header is ignored and the whole file is sent; `Some(None)`
+/// means no range can be satisfied (416).
+fn parse_ranges(header: &str, len: u64) -> Option<Option<Vec<(u64, u64)>>> {
+    let specs = header.trim().strip_prefix("bytes=")?;
+    let mut ranges = Vec::new();
+    for spec in specs.split(',') {
+        let (start, end) = spec.trim().split_once('-')?;
+        let range = match (start.trim(), end.trim()) {
+            ("", suffix) => {
+                let suffix: u64 = suffix.parse().ok()?;
+                (suffix > 0 && len > 0).then(|| (len.saturating_sub(suffix), len))
+            }
+            (start, "") => {
+                let start: u64 = start.parse().ok()?;
+                (start < len).then_some((start, len))
+            }
+            (start, end) => {
+                let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+                if end < start {
+                    return None;
+                }
+                (start < len).then(|| (start, end.min(len - 1) + 1))
+            }
+        };
+        ranges.extend(range);
+    }
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    let ranges = merged;
+    if ranges.len() > MAX_RANGES {
+        return None;
+    }
+    Some((!ranges.is_empty()).then_some(ranges))
+}
+
+async fn send(path: PathBuf, pieces: Vec<Piece>, sender: mpsc::Sender<std::io::Result<Bytes>>) {
+    let mut file = match File::open(&path).await {
+        Ok(file) => file,
+        Err(err) => {
+            _ = sender.send(Err(err)).await;
+            return;
+        }
+    };
+    for piece in pieces {
+        let (start, end) = match piece {
+            Piece::Bytes(bytes) => match sender.send(Ok(bytes)).await {
+                Ok(()) => continue,
+                Err(_) => return,
+            },
+            Piece::Range(start, end) => (start, end),
+        };
+        if let Err(err) = file.seek(SeekFrom::Start(start)).await {
+            _ = sender.send(Err(err)).await;
+            return;
+        }
+        let mut remaining = end - start;
+        while remaining > 0 {
+            let mut chunk = vec![0; remaining.min(CHUNK_SIZE) as usize];
+            let chunk = match file.read(&mut chunk).await {
+                // The file shrank since it was opened.
+                Ok(0) => Err(std::io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => {
+                    remaining -= read as u64;
+                    chunk.truncate(read);
+                    Ok(Bytes::from(chunk))
+                }
+                Err(err) => Err(err),
+            };
+            let failed = chunk.is_err();
+            if sender.send(chunk).await.is_err() || failed {
+                return;
+            }
+        }
+    }
+}
+
+/// Sends a file from disk without loading it into memory, with support for
+/// conditional and range requests.
+#[pyclass(extends=Response)]
+pub struct FileResponse;
+
+#[pymethods]
+impl FileResponse {
+    #[new]
+    #[pyo3(signature=(path, filename=None, as_attachment=false, content_type=None))]
+    fn new(
+        path: PathBuf,
+        filename: Option<String>,
+        as_attachment: bool,
+        content_type: Option<String>,
+        py: Python<'_>,
+    ) -> PyResult<(Self, Response)> {
+        let file = FileSource::open(path)?;
+        let content_type = match content_type {
+            Some(content_type) => content_type,
+            None => py
+                .import("mimetypes")?
+                .call_method1("guess_type", (&file.path,))?
+                .get_item(0)?
+                .extract::<Option<String>>()?
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+        };
+
+        let headers = Headers::with_content_type(&content_type)?;
+        headers.insert("accept-ranges", "bytes")?;
+        headers.insert("etag", &file.etag())?;
+        if let Some(modified) = file.modified {
+            headers.insert("last-modified", &httpdate::fmt_http_date(modified))?;
+        }
+        let filename = filename.or_else(|| {
+            as_attachment
+                .then(|| file.path.file_name())
+                .flatten()
+                .map(|name| name.to_string_lossy().into_owned())
+        });
+        if let Some(filename) = filename {
+            let kind = if as_attachment {
+                "attachment"
+            } else {
+                "inline"
+            };
+            headers.insert("content-disposition", &content_disposition(kind, &filename))?;
+        }
+
+        let response = Response {
+            status: Status::OK,
+            body: Bytes::new(),
+            headers,
+            stream: None,
+            file: Some(file),
+        };
+        Ok((Self, response))
+    }
+}
+
+/// `attachment; filename="..."`, with an RFC 5987 `filename*` when the name is
+/// not plain ASCII.
+fn content_disposition(kind: &str, filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    if fallback == filename {
+        return format!("{kind}; filename=\"{filename}\"");
+    }
+    let encoded: String = filename
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => (byte as char).to_string(),
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect();
+    format!("{kind}; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_ranges() {
+        assert_eq!(parse_ranges("bytes=0-99", 1000), Some(Some(vec![(0, 100)])));
+        assert_eq!(
+            parse_ranges("bytes=10-10", 1000),
+            Some(Some(vec![(10, 11)]))
+        );
+        // An end past the file is clamped to the last byte.
+        assert_eq!(
+            parse_ranges("bytes=900-5000", 1000),
+            Some(Some(vec![(900, 1000)]))
+        );
+        assert_eq!(
+            parse_ranges(" bytes= 5 - 9 ", 1000),
+            Some(Some(vec![(5, 10)]))
+        );
+    }
+
+    #[test]
+    fn open_ended_ranges() {
+        assert_eq!(
+            parse_ranges("bytes=500-", 1000),
+            Some(Some(vec![(500, 1000)]))
+        );
+        assert_eq!(
+            parse_ranges("bytes=999-", 1000),
+            Some(Some(vec![(999, 1000)]))
+        );
+        assert_eq!(parse_ranges("bytes=0-", 1000), Some(Some(vec![(0, 1000)])));
+    }
+
+    #[test]
+    fn suffix_ranges() {
+        assert_eq!(
+            parse_ranges("bytes=-100", 1000),
+            Some(Some(vec![(900, 1000)]))
+        );
+        // A suffix longer than the file selects all of it.
+        assert_eq!(
+            parse_ranges("bytes=-5000", 1000),
+            Some(Some(vec![(0, 1000)]))
+        );
+        assert_eq!(parse_ranges("bytes=-0", 1000), Some(None));
+        assert_eq!(parse_ranges("bytes=-10", 0), Some(None));
+    }
+
+    #[test]
+    fn overlapping_and_adjacent_ranges_merge() {
+        assert_eq!(
+            parse_ranges("bytes=0-99,50-149", 1000),
+            Some(Some(vec![(0, 150)]))
+        );
+        assert_eq!(
+            parse_ranges("bytes=0-99,100-199", 1000),
+            Some(Some(vec![(0, 200)]))
+        );
+        assert_eq!(
+            parse_ranges("bytes=500-599,0-99,-100", 1000),
+            Some(Some(vec![(0, 100), (500, 600), (900, 1000)]))
+        );
+        assert_eq!(
+            parse_ranges("bytes=0-99,101-199", 1000),
+            Some(Some(vec![(0, 100), (101, 200)]))
+        );
+        assert_eq!(
+            parse_ranges("bytes=10-20,0-", 1000),
+            Some(Some(vec![(0, 1000)]))
+        );
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(parse_ranges("bytes=1000-", 1000), Some(None));
+        assert_eq!(parse_ranges("bytes=1000-1999", 1000), Some(None));
+        assert_eq!(parse_ranges("bytes=0-0", 0), Some(None));
+        // Satisfiable specs are kept even next to ones past the end.
+        assert_eq!(
+            parse_ranges("bytes=2000-2999,0-9", 1000),
+            Some(Some(vec![(0, 10)]))
+        );
+    }
+
+    #[test]
+    fn malformed_ranges_are_ignored() {
+        assert_eq!(parse_ranges("bytes=", 1000), None);
+        assert_eq!(parse_ranges("bytes=abc", 1000), None);
+        assert_eq!(parse_ranges("bytes=5-1", 1000), None);
+        assert_eq!(parse_ranges("bytes=1-2-3", 1000), None);
+        assert_eq!(parse_ranges("bytes=x-10", 1000), None);
+        assert_eq!(parse_ranges("bytes=0-10,", 1000), None);
+        assert_eq!(parse_ranges("items=0-10", 1000), None);
+        assert_eq!(parse_ranges("0-10", 1000), None);
+    }
+
+    #[test]
+    fn too_many_ranges_are_ignored() {
+        let specs: Vec<String> = (0..=MAX_RANGES as u64)
+            .map(|i| format!("{}-{}", i * 10, i * 10 + 1))
+            .collect();
+        let header = format!("bytes={}", specs.join(","));
+        assert_eq!(parse_ranges(&header, 10_000), None);
+
+        // Ranges that merge below the limit are fine.
+        let specs: Vec<String> = (0..=MAX_RANGES as u64)
+            .map(|i| format!("{i}-{i}"))
+            .collect();
+        let header = format!("bytes={}", specs.join(","));
+        assert_eq!(
+            parse_ranges(&header, 10_000),
+            Some(Some(vec![(0, MAX_RANGES as u64 + 1)]))
+        );
+    }
+}
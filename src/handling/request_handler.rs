@@ -1,97 +1,264 @@
-use std::{mem::transmute, sync::Arc};
+use std::{future::Future, sync::Arc};
 
-use http_body_util::{BodyExt, Full};
+use http_body_util::{BodyExt, LengthLimitError, Limited};
 use hyper::{
-    body::{Bytes, Incoming},
-    Request as HyperRequest, Response as HyperResponse,
+    body::{Body, Incoming},
+    HeaderMap, Method, Request as HyperRequest, Response as HyperResponse, Uri, Version,
+};
+use tokio::{
+    sync::mpsc::channel,
+    time::{error::Elapsed, timeout_at, Instant},
 };
-use pyo3::{Py, PyAny};
-use tokio::sync::mpsc::{channel, Sender};
 
 use crate::{
-    cors::Cors, into_response::IntoResponse, request::Request, response::Response, routing::Router,
-    status::Status, MatchitRoute, ProcessRequest,
+    body::RequestBody,
+    cors::Cors,
+    decompression,
+    headers::Headers,
+    into_response::IntoResponse,
+    proxy::{Client, Peer},
+    request::Request,
+    response::Response,
+    status::Status,
+    streaming::ResponseBody,
+    ProcessRequest, ServerContext,
 };
 
 pub async fn handle_request(
     req: HyperRequest<Incoming>,
-    request_sender: Sender<ProcessRequest>,
-    routers: Vec<Arc<Router>>,
-    app_data: Option<Arc<Py<PyAny>>>,
-    channel_capacity: usize,
-    cors: Option<Arc<Cors>>,
-) -> Result<HyperResponse<Full<Bytes>>, hyper::http::Error> {
-    if req.method() == hyper::Method::OPTIONS && cors.is_some() {
-        let response = cors.as_ref().unwrap().into_response().unwrap();
-        return convert_to_hyper_response(response);
-    }
+    context: Arc<ServerContext>,
+    peer: Peer,
+) -> Result<HyperResponse<ResponseBody>, hyper::http::Error> {
+    let head = RequestHead {
+        method: req.method().clone(),
+        headers: req.headers().clone(),
+    };
+    let cors = context.cors.clone();
+    let deadline = context
+        .request_timeout
+        .and_then(|timeout| Instant::now().checked_add(timeout));
 
-    let request = convert_hyper_request(req).await.unwrap();
+    if let (true, Some(cors)) = (req.method() == hyper::Method::OPTIONS, &cors) {
+        let response = cors.into_response().unwrap();
+        return convert_to_hyper_response(response, &head);
+    }
 
-    for router in &routers {
-        if let Some(route) = router.find(&request.method, &request.uri) {
-            let (response_sender, mut respond_receive) = channel(channel_capacity);
+    let method = req.method().to_string();
+    let uri = origin_form(req.uri());
 
-            let route: MatchitRoute = unsafe { transmute(&route) };
+    let matched = context.routers.iter().find_map(|router| {
+        router
+            .find(&method, &uri)
+            .map(|(route, params)| (router.clone(), route, params))
+    });
+    let Some((router, route, params)) = matched else {
+        return status_response(&head, Status::NOT_FOUND, cors);
+    };
 
-            let process_request = ProcessRequest {
-                request: request.clone(),
-                router: router.clone(),
-                route,
-                response_sender,
-                app_data,
-                cors: cors.clone(),
+    let max_body_size = route.max_body_size.or(context.max_body_size);
+    let client = context.trusted_proxies.resolve(peer.addr, req.headers());
+    let stream = route.stream;
+    let body = convert_hyper_request(
+        req,
+        max_body_size,
+        context.max_decompressed_size,
+        stream,
+        peer,
+        client,
+    );
+    // A client that sends its body too slowly gets a 408 once the deadline
+    // passes, instead of tying up the connection.
+    let Ok(body) = within(deadline, body).await else {
+        return status_response(&head, Status::REQUEST_TIMEOUT, cors);
+    };
+    let request = match body {
+        Ok(request) => request,
+        Err(BodyError::TooLarge) => return status_response(&head, Status::PAYLOAD_TOO_LARGE, cors),
+        Err(BodyError::Invalid) => return status_response(&head, Status::BAD_REQUEST, cors),
+        Err(BodyError::Unsupported) => {
+            let response = Status::UNSUPPORTED_MEDIA_TYPE.into_response().unwrap();
+            _ = response.header("accept-encoding", decompression::SUPPORTED);
+            let response = match cors {
+                Some(cors) => cors.apply_to_response(response).unwrap(),
+                None => response,
             };
+            return convert_to_hyper_response(response, &head);
+        }
+    };
 
-            if request_sender.send(process_request).await.is_ok() {
-                if let Some(response) = respond_receive.recv().await {
-                    return convert_to_hyper_response(response);
-                }
+    let (response_sender, mut respond_receive) = channel(context.channel_capacity);
+
+    let process_request = ProcessRequest {
+        request,
+        router,
+        route,
+        params,
+        response_sender,
+        app_data: context.app_data.clone(),
+        cors: cors.clone(),
+    };
+
+    // Waiting for a free dispatch slot past the deadline means the server is
+    // overloaded (503); a handler that does not answer in time gets a 504.
+    let sent = match within(deadline, context.request_sender.send(process_request)).await {
+        Ok(sent) => sent,
+        Err(_) => return status_response(&head, Status::SERVICE_UNAVAILABLE, cors),
+    };
+    if sent.is_ok() {
+        match within(deadline, respond_receive.recv()).await {
+            Ok(Some(response)) => {
+                let response = convert_to_hyper_response(response, &head)?;
+                return Ok(match context.compression {
+                    Some(compression) => compression.apply(response, &head.headers).await,
+                    None => response,
+                });
             }
-            break;
+            Ok(None) => {}
+            Err(_) => return status_response(&head, Status::GATEWAY_TIMEOUT, cors),
         }
     }
 
-    let response = if let Some(cors_config) = cors {
-        cors_config.apply_to_response(Status::NOT_FOUND.into_response().unwrap())
-    } else {
-        Status::NOT_FOUND.into_response()
+    status_response(&head, Status::NOT_FOUND, cors)
+}
+
+pub async fn within<F: Future>(deadline: Option<Instant>, future: F) -> Result<F::Output, Elapsed> {
+    match deadline {
+        Some(deadline) => timeout_at(deadline, future).await,
+        None => Ok(future.await),
+    }
+}
+
+fn status_response(
+    head: &RequestHead,
+    status: Status,
+    cors: Option<Arc<Cors>>,
+) -> Result<HyperResponse<ResponseBody>, hyper::http::Error> {
+    let response = status.into_response().unwrap();
+    let response = match cors {
+        Some(cors) => cors.apply_to_response(response).unwrap(),
+        None => response,
     };
+    convert_to_hyper_response(response, head)
+}
 
-    convert_to_hyper_response(response.unwrap())
+/// The parts of the request that shape how the response is sent.
+struct RequestHead {
+    method: Method,
+    headers: HeaderMap,
+}
+
+enum BodyError {
+    TooLarge,
+    Invalid,
+    Unsupported,
+}
+
+// HTTP/2 requests carry an absolute URI; keep only the origin form like HTTP/1.1.
+fn origin_form(uri: &Uri) -> String {
+    uri.path_and_query()
+        .map_or_else(|| "/".to_string(), |path| path.to_string())
+}
+
+fn http_version(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "HTTP/0.9",
+        Version::HTTP_10 => "HTTP/1.0",
+        Version::HTTP_2 => "HTTP/2",
+        Version::HTTP_3 => "HTTP/3",
+        _ => "HTTP/1.1",
+    }
 }
 
 async fn convert_hyper_request(
     req: HyperRequest<Incoming>,
-) -> Result<Request, Box<dyn std::error::Error + Sync + Send>> {
+    max_body_size: Option<usize>,
+    max_decompressed_size: Option<usize>,
+    stream: bool,
+    peer: Peer,
+    client: Client,
+) -> Result<Request, BodyError> {
     let method = req.method().to_string();
-    let uri = req.uri().to_string();
-
-    let mut headers = std::collections::HashMap::new();
-    for (key, value) in req.headers() {
-        headers.insert(
-            key.to_string(),
-            value.to_str().unwrap_or_default().to_string(),
-        );
-    }
+    let uri = origin_form(req.uri());
 
-    let mut request = Request::new(method, uri, headers);
+    let headers = Headers::from(req.headers().clone());
+    let mut request = Request::from_parts(method, uri, headers);
+    request.client_addr = client.addr;
+    request.scheme = client
+        .scheme
+        .unwrap_or_else(|| if peer.secure { "https" } else { "http" }.to_string());
+    request.http_version = http_version(req.version()).to_string();
+    request.host = client.host.or_else(|| {
+        let host = req.headers().get(hyper::header::HOST);
+        host.and_then(|host| host.to_str().ok())
+            .or_else(|| req.uri().authority().map(|authority| authority.as_str()))
+            .map(str::to_string)
+    });
 
-    let body_bytes = req.collect().await?.to_bytes();
-    let body = String::from_utf8_lossy(&body_bytes).to_string();
-    if !body.is_empty() {
-        request.set_body(body);
+    // Reject on the declared Content-Length before reading anything.
+    if max_body_size.is_some_and(|limit| req.body().size_hint().lower() > limit as u64) {
+        return Err(BodyError::TooLarge);
+    }
+    let encodings = decompression::encodings(req.headers()).map_err(|_| BodyError::Unsupported)?;
+    if !encodings.is_empty() {
+        // Handlers see the decoded body, so the limit applies to it.
+        let limit = max_body_size.or(max_decompressed_size);
+        let reader = decompression::decode(req.into_body(), &encodings);
+        {
+            let mut headers = request.headers.lock();
+            headers.remove(hyper::header::CONTENT_ENCODING);
+            headers.remove(hyper::header::CONTENT_LENGTH);
+        }
+        if stream {
+            request.set_stream(RequestBody::spawn_decoded(reader, limit));
+            return Ok(request);
+        }
+        let body = decompression::read(reader, limit)
+            .await
+            .map_err(|_| BodyError::Invalid)?
+            .ok_or(BodyError::TooLarge)?;
+        request.set_body(body.into());
+        return Ok(request);
+    }
+    if stream {
+        request.set_stream(RequestBody::spawn(req.into_body(), max_body_size));
+        return Ok(request);
     }
 
+    let body_bytes = match max_body_size {
+        Some(limit) => Limited::new(req.into_body(), limit)
+            .collect()
+            .await
+            .map_err(|err| match err.downcast_ref::<LengthLimitError>() {
+                Some(_) => BodyError::TooLarge,
+                None => BodyError::Invalid,
+            })?
+            .to_bytes(),
+        None => req
+            .collect()
+            .await
+            .map_err(|_| BodyError::Invalid)?
+            .to_bytes(),
+    };
+
+    request.set_body(body_bytes);
     Ok(request)
 }
 
 fn convert_to_hyper_response(
     response: Response,
-) -> Result<HyperResponse<Full<Bytes>>, hyper::http::Error> {
+    request: &RequestHead,
+) -> Result<HyperResponse<ResponseBody>, hyper::http::Error> {
+    if let Some(file) = &response.file {
+        let headers = response.headers.lock().clone();
+        let status = response.status.code();
+        return file.respond(status, headers, &request.method, &request.headers);
+    }
     let mut response_builder = HyperResponse::builder().status(response.status.code());
-    for (key, value) in response.headers {
-        response_builder = response_builder.header(key, value);
+    if let Some(headers) = response_builder.headers_mut() {
+        headers.extend(response.headers.lock().clone());
+    }
+    match response.stream {
+        Some(stream) => response_builder.body(stream.spawn()),
+        None => response_builder.body(ResponseBody::Full(response.body)),
     }
-    response_builder.body(Full::new(response.body))
 }
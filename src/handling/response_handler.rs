@@ -1,81 +1,221 @@
 use std::sync::Arc;
 
 use pyo3::{
-    types::{PyAnyMethods, PyDict},
-    Bound, Py, PyAny, PyResult, Python,
+    types::{PyAnyMethods, PyBytes, PyDict, PyDictMethods, PyList, PyListMethods},
+    Bound, IntoPyObjectExt, Py, PyAny, PyResult, Python,
+};
+use tokio::{
+    sync::{mpsc::Receiver, OwnedSemaphorePermit, Semaphore},
+    time::Instant,
 };
-use tokio::sync::mpsc::Receiver;
 
 use crate::{
+    coroutine,
+    handling::request_handler::within,
     into_response::{convert_to_response, IntoResponse},
     middleware::MiddlewareChain,
-    request::Request,
+    params,
+    request::{Request, URLENCODED},
     response::Response,
     routing::{Route, Router},
     status::Status,
-    MatchitRoute, ProcessRequest,
+    to_py_exception, ProcessRequest,
 };
 
-pub async fn handle_response(shutdown_rx: &mut Receiver<()>, rx: &mut Receiver<ProcessRequest>) {
+// Runs until `shutdown_rx` delivers the drain deadline, then waits for running
+// handlers until that deadline; `None` waits for as long as they take.
+pub async fn handle_response(
+    shutdown_rx: &mut Receiver<Option<Instant>>,
+    rx: &mut Receiver<ProcessRequest>,
+    workers: usize,
+) {
+    let pool = Arc::new(Semaphore::new(workers));
+    let mut deadline = None;
+
     loop {
-        tokio::select! {
-            Some(process_request) = rx.recv() => {
-                let response = match process_response(
-                    &process_request.router,
-                    process_request.route,
-                    &process_request.request,
-                    process_request.app_data,
-                ) {
-                    Ok(response) => response,
-                    Err(e) => Status::INTERNAL_SERVER_ERROR
-                        .into_response()
-                        .unwrap()
-                        .body(e.to_string()),
-                };
+        // Take a worker slot before receiving, so a shutdown is still noticed
+        // while every worker is busy.
+        let permit = tokio::select! {
+            permit = pool.clone().acquire_owned() => permit.unwrap(),
+            stop = shutdown_rx.recv() => {
+                deadline = stop.flatten();
+                break;
+            }
+        };
 
-                let final_response = if let Some(cors) = process_request.cors {
-                    cors.apply_to_response(response).unwrap()
-                } else {
-                    response
-                };
+        tokio::select! {
+            process_request = rx.recv() => {
+                let Some(process_request) = process_request else { break };
 
-                _ = process_request.response_sender.send(final_response).await;
+                tokio::spawn(async move {
+                    let response_sender = process_request.response_sender.clone();
+                    let response = dispatch(process_request, permit).await;
+                    _ = response_sender.send(response).await;
+                });
+            }
+            stop = shutdown_rx.recv() => {
+                deadline = stop.flatten();
+                break;
             }
-            _ = shutdown_rx.recv() => {break}
         }
     }
+
+    // Wait for handlers that are still running before the runtime goes away.
+    if within(deadline, pool.acquire_many(workers as u32))
+        .await
+        .is_err()
+    {
+        println!("Drain timeout exceeded, abandoning running handlers");
+    }
+}
+
+enum Outcome {
+    Ready(Response),
+    Pending(coroutine::Pending),
+}
+
+async fn dispatch(process_request: ProcessRequest, permit: OwnedSemaphorePermit) -> Response {
+    let cors = process_request.cors.clone();
+
+    let outcome = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        process_response(
+            &process_request.router,
+            &process_request.route,
+            &process_request.params,
+            &process_request.request,
+            process_request.app_data,
+        )
+    })
+    .await;
+
+    // The worker is released before awaiting a coroutine, so it keeps serving
+    // other requests while the coroutine runs on the asyncio loop.
+    let response = match to_py_exception(outcome).and_then(|outcome| outcome) {
+        Ok(Outcome::Ready(response)) => Ok(response),
+        Ok(Outcome::Pending(pending)) => resolve(pending).await,
+        Err(e) => Err(e),
+    };
+
+    let response = response.unwrap_or_else(|e| {
+        Status::INTERNAL_SERVER_ERROR
+            .into_response()
+            .unwrap()
+            .body(e.to_string())
+    });
+
+    if let Some(cors) = cors {
+        cors.apply_to_response(response).unwrap()
+    } else {
+        response
+    }
+}
+
+async fn resolve(pending: coroutine::Pending) -> PyResult<Response> {
+    let result = to_py_exception(pending.await)??;
+    let response =
+        tokio::task::spawn_blocking(move || Python::with_gil(|py| convert_to_response(result, py)))
+            .await;
+    to_py_exception(response)?
 }
 
 fn process_response(
     router: &Router,
-    matchit_route: MatchitRoute,
+    route: &Route,
+    params: &[(String, String)],
     request: &Request,
     app_data: Option<Arc<Py<PyAny>>>,
-) -> PyResult<Response> {
+) -> PyResult<Outcome> {
     Python::with_gil(|py| {
         let kwargs = &PyDict::new(py);
-        let params = &matchit_route.params;
-        let route = matchit_route.value;
         let app_data = app_data.clone();
 
-        setup_params(kwargs, params)?;
+        let errors = &PyList::empty(py);
+        setup_params(route, kwargs, params, errors)?;
         setup_app_data(app_data, route, kwargs, py)?;
-        setup_body(route, kwargs, request, py)?;
+        setup_body(route, kwargs, request, errors)?;
+        setup_query(route, kwargs, request, errors)?;
+        if !errors.is_empty() {
+            return Ok(Outcome::Ready(params::error_response(errors.clone())?));
+        }
+
+        // Middleware and handler share one `Request` object.
+        let request = Py::new(py, request.clone())?;
+        let handler = setup_request(route, &request, py)?;
 
         let result = if !router.middlewares.is_empty() {
             let chain = MiddlewareChain::new(router.middlewares.clone());
-            chain.execute(py, request, &route.handler.clone(), kwargs.clone())?
+            chain.execute(py, &request, &handler, kwargs.clone())?
         } else {
-            route.handler.call(py, (), Some(kwargs))?
+            handler.call(py, (), Some(kwargs))?
         };
 
-        convert_to_response(result, py)
+        if coroutine::is_awaitable(result.bind(py))? {
+            return Ok(Outcome::Pending(coroutine::spawn(result.bind(py))?));
+        }
+
+        Ok(Outcome::Ready(convert_to_response(result, py)?))
     })
 }
 
-fn setup_params(kwargs: &Bound<'_, PyDict>, params: &matchit::Params<'_, '_>) -> PyResult<()> {
-    for (key, value) in params.iter() {
-        kwargs.set_item(key, value)?;
+fn setup_params(
+    route: &Route,
+    kwargs: &Bound<'_, PyDict>,
+    params: &[(String, String)],
+    errors: &Bound<'_, PyList>,
+) -> PyResult<()> {
+    let py = errors.py();
+    for (key, value) in params {
+        let Some(annotation) = route.annotations.get(key) else {
+            kwargs.set_item(key, value)?;
+            continue;
+        };
+        match params::convert(value, annotation.bind(py)) {
+            Ok(value) => kwargs.set_item(key, value)?,
+            Err(err) => {
+                params::push_error(errors, err, &["path", key], value.into_bound_py_any(py)?)?
+            }
+        }
+    }
+    Ok(())
+}
+
+// The arguments a route lists in `query=` are looked up in the query string.
+// Absent ones are left to the argument's default.
+fn setup_query(
+    route: &Route,
+    kwargs: &Bound<'_, PyDict>,
+    request: &Request,
+    errors: &Bound<'_, PyList>,
+) -> PyResult<()> {
+    let py = errors.py();
+    if route.query.is_empty() {
+        return Ok(());
+    }
+    let query = request.query_pairs();
+    for name in route.query.iter() {
+        let values: Vec<String> = query
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+            .collect();
+        let Some(last) = values.last() else {
+            continue;
+        };
+        let Some(annotation) = route.annotations.get(name) else {
+            kwargs.set_item(name, last)?;
+            continue;
+        };
+        match params::convert_query(values.clone(), annotation.bind(py)) {
+            Ok(value) => kwargs.set_item(name, value)?,
+            Err(err) => {
+                let input = match values.len() {
+                    1 => last.into_bound_py_any(py)?,
+                    _ => values.into_bound_py_any(py)?,
+                };
+                params::push_error(errors, err, &["query", name], input)?;
+            }
+        }
     }
     Ok(())
 }
@@ -92,16 +232,70 @@ fn setup_app_data(
     Ok(())
 }
 
+// The request is bound to the handler rather than put in `kwargs`, since
+// middleware receives it as a named argument and does not pass it on.
+fn setup_request(route: &Route, request: &Py<Request>, py: Python<'_>) -> PyResult<Py<PyAny>> {
+    if !route.args.iter().any(|arg| arg == "request") {
+        return Ok(route.handler.clone_ref(py));
+    }
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("request", request.clone_ref(py))?;
+    let partial = py.import("functools")?.getattr("partial")?;
+    Ok(partial
+        .call((route.handler.clone_ref(py),), Some(&kwargs))?
+        .unbind())
+}
+
 fn setup_body(
     route: &Route,
     kwargs: &Bound<'_, PyDict>,
     request: &Request,
-    py: Python<'_>,
+    errors: &Bound<'_, PyList>,
 ) -> PyResult<()> {
-    if let Some(body_name) = &route.data {
-        match route.content_type.as_str() {
-            "application/json" => kwargs.set_item(body_name, request.json(py)?)?,
-            _ => kwargs.set_item(body_name, request.body.clone())?,
+    let py = errors.py();
+    if let (Some(body_name), Some(stream)) = (&route.data, &request.stream) {
+        kwargs.set_item(body_name, stream.clone())?;
+    } else if let Some(body_name) = &route.data {
+        let body = match route.content_type.as_str() {
+            "application/json" => match request.json(py) {
+                Ok(body) => body.into_bound(py),
+                Err(err) => {
+                    let input = String::from_utf8_lossy(&request.body).into_bound_py_any(py)?;
+                    return params::push_error(errors, err, &["body"], input);
+                }
+            },
+            URLENCODED => request.form(py)?.into_bound_py_any(py)?,
+            content_type if content_type.starts_with("text/") => {
+                request.text(None, py)?.into_bound_py_any(py)?
+            }
+            _ => PyBytes::new(py, &request.body).into_any(),
+        };
+        let body = match route.annotations.get(body_name) {
+            Some(annotation) => {
+                let coerce = route.content_type == URLENCODED;
+                params::validate_body(body, annotation.bind(py), coerce, errors)?
+            }
+            None => Some(body),
+        };
+        if let Some(body) = body {
+            kwargs.set_item(body_name, body)?;
+        }
+    }
+    // A malformed multipart body only matters to routes that read it.
+    if let Some(form_name) = &route.form {
+        match request.form(py) {
+            Ok(form) => kwargs.set_item(form_name, form)?,
+            Err(err) => {
+                return params::push_error(errors, err, &["body"], py.None().into_bound(py))
+            }
+        }
+    }
+    if let Some(files_name) = &route.files {
+        match request.files(py) {
+            Ok(files) => kwargs.set_item(files_name, files)?,
+            Err(err) => {
+                return params::push_error(errors, err, &["body"], py.None().into_bound(py))
+            }
         }
     }
     Ok(())
@@ -0,0 +1,207 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use pyo3::{
+    exceptions::{PyKeyError, PyValueError},
+    prelude::*,
+    types::{PyIterator, PyList},
+};
+
+/// Case-insensitive, multi-value HTTP headers backed by a `hyper::HeaderMap`.
+///
+/// The `headers` attribute of a request or response is a live view: changes
+/// made through it are seen by the object it came from.
+#[pyclass]
+pub struct Headers {
+    map: Arc<Mutex<HeaderMap>>,
+}
+
+/// Anything `Headers(...)` accepts: another `Headers`, a dict or a list of pairs.
+#[derive(FromPyObject)]
+pub enum HeaderItems {
+    Headers(Headers),
+    Map(HashMap<String, String>),
+    Pairs(Vec<(String, String)>),
+}
+
+impl TryFrom<HeaderItems> for Headers {
+    type Error = PyErr;
+
+    fn try_from(items: HeaderItems) -> PyResult<Self> {
+        let pairs = match items {
+            HeaderItems::Headers(headers) => return Ok(headers),
+            HeaderItems::Map(map) => map.into_iter().collect(),
+            HeaderItems::Pairs(pairs) => pairs,
+        };
+        let headers = Headers::default();
+        for (name, value) in pairs {
+            headers.append(&name, &value)?;
+        }
+        Ok(headers)
+    }
+}
+
+/// Clones the headers themselves; use `share` for another handle on the same map.
+impl Clone for Headers {
+    fn clone(&self) -> Self {
+        Self::from(self.lock().clone())
+    }
+}
+
+impl Default for Headers {
+    fn default() -> Self {
+        Self::from(HeaderMap::new())
+    }
+}
+
+impl From<HeaderMap> for Headers {
+    fn from(map: HeaderMap) -> Self {
+        Self {
+            map: Arc::new(Mutex::new(map)),
+        }
+    }
+}
+
+impl std::fmt::Debug for Headers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.lock().fmt(f)
+    }
+}
+
+impl Headers {
+    pub fn with_content_type(content_type: &str) -> PyResult<Self> {
+        let headers = Self::default();
+        headers.insert("content-type", content_type)?;
+        Ok(headers)
+    }
+
+    pub fn share(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, HeaderMap> {
+        self.map.lock().unwrap()
+    }
+
+    /// The first value of `name`, decoded.
+    pub fn get_str(&self, name: &str) -> Option<String> {
+        self.lock().get(name).map(decode)
+    }
+
+    /// Replaces every value of `name` with `value`.
+    pub fn insert(&self, name: &str, value: &str) -> PyResult<()> {
+        let (name, value) = parse(name, value)?;
+        self.lock().insert(name, value);
+        Ok(())
+    }
+
+    /// Adds `value` to `name`, keeping the values already present.
+    pub fn append(&self, name: &str, value: &str) -> PyResult<()> {
+        let (name, value) = parse(name, value)?;
+        self.lock().append(name, value);
+        Ok(())
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.lock().keys().map(ToString::to_string).collect()
+    }
+}
+
+#[pymethods]
+impl Headers {
+    #[new]
+    #[pyo3(signature=(items=None))]
+    fn new(items: Option<HeaderItems>) -> PyResult<Self> {
+        items.map_or_else(|| Ok(Self::default()), Self::try_from)
+    }
+
+    /// The first value of `name`, or `default` when it is absent.
+    #[pyo3(signature=(name, default=None))]
+    fn get(&self, name: &str, default: Option<String>) -> Option<String> {
+        self.get_str(name).or(default)
+    }
+
+    fn getlist(&self, name: &str) -> Vec<String> {
+        self.lock().get_all(name).iter().map(decode).collect()
+    }
+
+    fn add(&self, name: &str, value: &str) -> PyResult<()> {
+        self.append(name, value)
+    }
+
+    fn set(&self, name: &str, value: &str) -> PyResult<()> {
+        self.insert(name, value)
+    }
+
+    /// Removes every value of `name`; absent names are ignored.
+    fn remove(&self, name: &str) {
+        self.lock().remove(name);
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.names()
+    }
+
+    /// Every `(name, value)` pair, with one entry per value.
+    fn items(&self) -> Vec<(String, String)> {
+        self.lock()
+            .iter()
+            .map(|(name, value)| (name.to_string(), decode(value)))
+            .collect()
+    }
+
+    fn __getitem__(&self, name: &str) -> PyResult<String> {
+        self.get_str(name)
+            .ok_or_else(|| PyKeyError::new_err(name.to_string()))
+    }
+
+    fn __setitem__(&self, name: &str, value: &str) -> PyResult<()> {
+        self.insert(name, value)
+    }
+
+    fn __delitem__(&self, name: &str) -> PyResult<()> {
+        match self.lock().remove(name) {
+            Some(_) => Ok(()),
+            None => Err(PyKeyError::new_err(name.to_string())),
+        }
+    }
+
+    fn __contains__(&self, name: &str) -> bool {
+        self.lock().contains_key(name)
+    }
+
+    fn __len__(&self) -> usize {
+        self.lock().keys_len()
+    }
+
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        PyList::new(py, self.names())?.try_iter()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Headers({:?})", self.items())
+    }
+}
+
+fn parse(name: &str, value: &str) -> PyResult<(HeaderName, HeaderValue)> {
+    let name = HeaderName::from_bytes(name.as_bytes())
+        .map_err(|_| PyValueError::new_err(format!("Invalid header name: {name:?}")))?;
+    let value = HeaderValue::from_str(value)
+        .map_err(|_| PyValueError::new_err(format!("Invalid value for header {name}")))?;
+    Ok((name, value))
+}
+
+/// Header values are usually ASCII; anything else is read as UTF-8 when
+/// valid and as ISO-8859-1 otherwise, so no value is ever lost.
+fn decode(value: &HeaderValue) -> String {
+    match value.to_str() {
+        Ok(value) => value.to_string(),
+        Err(_) => String::from_utf8(value.as_bytes().to_vec())
+            .unwrap_or_else(|_| value.as_bytes().iter().map(|&b| b as char).collect()),
+    }
+}
@@ -1,6 +1,4 @@
-use std::collections::HashMap;
-
-use crate::{status::Status, Response};
+use crate::{headers::Headers, status::Status, Response};
 use pyo3::{prelude::*, types::PyAny, Py};
 
 pub trait IntoResponse {
@@ -12,8 +10,10 @@ impl IntoResponse for String {
     fn into_response(&self) -> PyResult<Response> {
         Ok(Response {
             status: Status::OK,
-            headers: HashMap::from([("Content-Type".to_string(), "text/plain".to_string())]),
+            headers: Headers::with_content_type("text/plain")?,
             body: self.clone().into(),
+            stream: None,
+            file: None,
         })
     }
 }
@@ -22,8 +22,10 @@ impl IntoResponse for PyObject {
     fn into_response(&self) -> PyResult<Response> {
         Ok(Response {
             status: Status::OK,
-            headers: HashMap::from([("Content-Type".to_string(), "application/json".to_string())]),
+            headers: Headers::with_content_type("application/json")?,
             body: crate::json::dumps(self)?.into(),
+            stream: None,
+            file: None,
         })
     }
 }
@@ -32,8 +34,10 @@ impl IntoResponse for (String, Status) {
     fn into_response(&self) -> PyResult<Response> {
         Ok(Response {
             status: self.1.clone(),
-            headers: HashMap::from([("Content-Type".to_string(), "text/plain".to_string())]),
+            headers: Headers::with_content_type("text/plain")?,
             body: self.0.clone().into(),
+            stream: None,
+            file: None,
         })
     }
 }
@@ -42,8 +46,10 @@ impl IntoResponse for (PyObject, Status) {
     fn into_response(&self) -> PyResult<Response> {
         Ok(Response {
             status: self.1.clone(),
-            headers: HashMap::from([("Content-Type".to_string(), "application/json".to_string())]),
+            headers: Headers::with_content_type("application/json")?,
             body: crate::json::dumps(&self.0)?.into(),
+            stream: None,
+            file: None,
         })
     }
 }
@@ -1,6 +1,6 @@
 use pyo3::{
-    types::{PyAnyMethods, PyDict, PyModule},
-    Py, PyObject, PyResult, Python,
+    types::{PyAnyMethods, PyBytes, PyModule},
+    Py, PyAny, PyObject, PyResult, Python,
 };
 
 pub fn dumps(data: &PyObject) -> PyResult<String> {
@@ -13,10 +13,10 @@ pub fn dumps(data: &PyObject) -> PyResult<String> {
     })
 }
 
-pub fn loads(data: &str) -> PyResult<Py<PyDict>> {
+pub fn loads(data: &[u8]) -> PyResult<Py<PyAny>> {
     Python::with_gil(|py| {
         let orjson_module = PyModule::import(py, "orjson")?;
-        let deserialized_data = orjson_module.call_method1("loads", (data,))?;
-        deserialized_data.extract()
+        let deserialized_data = orjson_module.call_method1("loads", (PyBytes::new(py, data),))?;
+        Ok(deserialized_data.unbind())
     })
 }
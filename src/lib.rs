@@ -1,53 +1,111 @@
+mod body;
+mod compression;
+mod connection;
+mod cookie;
+mod coroutine;
 mod cors;
+mod decompression;
+mod file_response;
 mod handling;
+mod headers;
 mod into_response;
 mod json;
+mod listener;
 mod middleware;
+mod multidict;
+mod multipart;
+mod params;
+mod prefork;
+mod proxy;
 mod request;
 mod response;
 mod routing;
+mod server_handle;
+mod shutdown;
+mod sse;
 mod status;
+mod stream;
+mod streaming;
 mod templating;
+mod tls;
 
+use body::RequestBody;
+use compression::Compression;
+use connection::{ConnectionState, TrackedIo};
 use cors::Cors;
-use handling::request_handler::handle_request;
+use file_response::FileResponse;
+use handling::request_handler::{handle_request, within};
 use handling::response_handler::handle_response;
-use pyo3::exceptions::PyException;
+use headers::Headers;
+use listener::{Address, Bind, BindAddr, Listener, StdListener};
+use multidict::MultiDict;
+use multipart::UploadFile;
+use proxy::{Peer, TrustedProxies};
+use pyo3::exceptions::{PyException, PyKeyboardInterrupt, PyValueError};
 use request::Request;
 use response::Response;
-use routing::{delete, get, patch, post, put, static_file, Route, Router};
+use routing::{delete, get, patch, post, put, static_file, Params, Route, Router};
+use server_handle::ServerHandle;
+use sse::{Event, EventStream};
 use status::Status;
+use stream::Stream;
+use streaming::StreamingResponse;
+use tls::PemSource;
 
-use hyper::server::conn::http1;
 use hyper::service::service_fn;
-use hyper_util::rt::TokioIo;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
 
-use matchit::Match;
 use templating::templating_submodule;
-use tokio::net::TcpListener;
 use tokio::sync::mpsc::{channel, Sender};
-use tokio::sync::Semaphore;
+use tokio::sync::{oneshot, watch, Semaphore};
+use tokio::time::Instant;
+use tokio_rustls::TlsAcceptor;
 
-use std::{
-    net::SocketAddr,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
-};
+use std::{future::Future, path::PathBuf, sync::Arc, time::Duration};
 
 use pyo3::prelude::*;
 
-type MatchitRoute = &'static Match<'static, 'static, &'static Route>;
+/// Compressed request bodies may decode to at most this many bytes when no
+/// `max_body_size` applies.
+const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+/// How long a stopped server waits for leftover tasks, such as handlers still
+/// running past the drain timeout, before abandoning them.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(1);
+
+/// HTTP/2 streams a client may open at once, the same default hyper uses.
+const DEFAULT_MAX_CONCURRENT_STREAMS: u32 = 200;
 
 fn to_py_exception<T, E: ToString>(result: Result<T, E>) -> PyResult<T> {
     result.map_err(|err| PyException::new_err(err.to_string()))
 }
 
+fn seconds(value: Option<f64>) -> PyResult<Option<Duration>> {
+    value
+        .map(|secs| to_py_exception(Duration::try_from_secs_f64(secs)))
+        .transpose()
+}
+
+/// State shared by every connection of a running server.
+struct ServerContext {
+    request_sender: Sender<ProcessRequest>,
+    routers: Vec<Arc<Router>>,
+    app_data: Option<Arc<Py<PyAny>>>,
+    channel_capacity: usize,
+    cors: Option<Arc<Cors>>,
+    request_timeout: Option<Duration>,
+    max_body_size: Option<usize>,
+    trusted_proxies: TrustedProxies,
+    compression: Option<Compression>,
+    max_decompressed_size: Option<usize>,
+}
+
 struct ProcessRequest {
     request: Request,
     router: Arc<Router>,
-    route: MatchitRoute,
+    route: Arc<Route>,
+    params: Params,
     response_sender: Sender<Response>,
     app_data: Option<Arc<Py<PyAny>>>,
     cors: Option<Arc<Cors>>,
@@ -56,26 +114,53 @@ struct ProcessRequest {
 #[derive(Clone)]
 #[pyclass]
 struct HttpServer {
-    addr: SocketAddr,
+    binds: Vec<Bind>,
     routers: Vec<Arc<Router>>,
     app_data: Option<Arc<Py<PyAny>>>,
     max_connections: Arc<Semaphore>,
     channel_capacity: usize,
     cors_header: Option<Arc<Cors>>,
+    http2_max_concurrent_streams: Option<u32>,
+    http2_initial_stream_window_size: Option<u32>,
+    http2_initial_connection_window_size: Option<u32>,
+    tls: Option<TlsAcceptor>,
+    drain_timeout: Duration,
+    workers: usize,
+    header_read_timeout: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+    max_requests_per_connection: Option<usize>,
+    request_timeout: Option<Duration>,
+    max_body_size: Option<usize>,
+    trusted_proxies: TrustedProxies,
+    compression: Option<Compression>,
+    max_decompressed_size: Option<usize>,
 }
 
 #[pymethods]
 impl HttpServer {
     #[new]
-    fn new(addr: (String, u16)) -> PyResult<Self> {
-        let (ip, port) = addr;
+    fn new(addr: BindAddr) -> PyResult<Self> {
         Ok(Self {
-            addr: SocketAddr::new(ip.parse()?, port),
+            binds: vec![addr.try_into()?],
             routers: Vec::new(),
             app_data: None,
             max_connections: Arc::new(Semaphore::new(100)),
             channel_capacity: 100,
             cors_header: None,
+            http2_max_concurrent_streams: Some(DEFAULT_MAX_CONCURRENT_STREAMS),
+            http2_initial_stream_window_size: None,
+            http2_initial_connection_window_size: None,
+            tls: None,
+            drain_timeout: Duration::from_secs(30),
+            workers: 1,
+            header_read_timeout: Some(Duration::from_secs(30)),
+            keep_alive_timeout: Some(Duration::from_secs(60)),
+            max_requests_per_connection: None,
+            request_timeout: None,
+            max_body_size: None,
+            trusted_proxies: TrustedProxies::default(),
+            compression: None,
+            max_decompressed_size: Some(DEFAULT_MAX_DECOMPRESSED_SIZE),
         })
     }
 
@@ -87,97 +172,350 @@ impl HttpServer {
         self.routers.push(Arc::new(router.clone()));
     }
 
-    fn run(&self) -> PyResult<()> {
-        let runtime = tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()?;
-        runtime.block_on(async move { self.run_server().await })?;
+    fn bind(&mut self, addr: BindAddr) -> PyResult<()> {
+        self.binds.push(addr.try_into()?);
+        Ok(())
+    }
+
+    #[pyo3(signature=(path, mode=None, owner=None, group=None))]
+    fn bind_unix(
+        &mut self,
+        path: PathBuf,
+        mode: Option<u32>,
+        owner: Option<u32>,
+        group: Option<u32>,
+    ) {
+        self.binds.push(Bind::Unix {
+            path,
+            mode,
+            owner,
+            group,
+        });
+    }
+
+    fn tls(&mut self, cert: PemSource, key: PemSource) -> PyResult<()> {
+        self.tls = Some(tls::build_acceptor(cert, key)?);
         Ok(())
     }
 
-    #[pyo3(signature=(max_connections = 100, channel_capacity = 100, cors=None))]
+    #[pyo3(signature=(processes=1))]
+    fn run(&self, processes: usize, py: Python<'_>) -> PyResult<()> {
+        let listeners = self.bind_listeners()?;
+        let result = if processes > 1 {
+            prefork::run(self, listeners, processes, py)
+        } else {
+            self.serve(listeners, py)
+        };
+        self.remove_socket_files();
+        result
+    }
+
+    fn start(&self) -> PyResult<ServerHandle> {
+        let listeners = self.bind_listeners()?;
+        let addresses = listeners
+            .iter()
+            .map(StdListener::local_addr)
+            .collect::<std::io::Result<_>>()?;
+
+        let server = self.clone();
+        let (stop_tx, stop_rx) = oneshot::channel::<Option<Duration>>();
+
+        let thread = std::thread::spawn(move || -> PyResult<()> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            let result = runtime.block_on(async {
+                // A dropped handle leaves the server running, like a detached thread.
+                let shutdown = async move {
+                    match stop_rx.await {
+                        Ok(timeout) => timeout,
+                        Err(_) => std::future::pending().await,
+                    }
+                };
+                server.run_server(listeners, shutdown).await
+            });
+            runtime.shutdown_timeout(SHUTDOWN_GRACE);
+            server.remove_socket_files();
+            result
+        });
+        Ok(ServerHandle::new(addresses, stop_tx, thread))
+    }
+
+    #[pyo3(signature=(
+        max_connections = 100,
+        channel_capacity = 100,
+        cors=None,
+        http2_max_concurrent_streams=Some(DEFAULT_MAX_CONCURRENT_STREAMS),
+        http2_initial_stream_window_size=None,
+        http2_initial_connection_window_size=None,
+        drain_timeout=30.0,
+        workers=1,
+        header_read_timeout=Some(30.0),
+        keep_alive_timeout=Some(60.0),
+        max_requests_per_connection=None,
+        request_timeout=None,
+        max_body_size=None,
+        trusted_proxies=None,
+        compression=false,
+        compression_min_size=1024,
+        max_decompressed_size=Some(DEFAULT_MAX_DECOMPRESSED_SIZE),
+    ))]
+    #[allow(clippy::too_many_arguments)]
     fn config(
         &mut self,
         max_connections: usize,
         channel_capacity: usize,
         cors: Option<PyRef<Cors>>,
+        http2_max_concurrent_streams: Option<u32>,
+        http2_initial_stream_window_size: Option<u32>,
+        http2_initial_connection_window_size: Option<u32>,
+        drain_timeout: f64,
+        workers: usize,
+        header_read_timeout: Option<f64>,
+        keep_alive_timeout: Option<f64>,
+        max_requests_per_connection: Option<usize>,
+        request_timeout: Option<f64>,
+        max_body_size: Option<usize>,
+        trusted_proxies: Option<Vec<String>>,
+        compression: bool,
+        compression_min_size: usize,
+        max_decompressed_size: Option<usize>,
     ) -> PyResult<()> {
+        if workers == 0 {
+            return Err(PyValueError::new_err("workers must be at least 1"));
+        }
         self.max_connections = Arc::new(Semaphore::new(max_connections));
         self.channel_capacity = channel_capacity;
         self.cors_header = cors.map(|c| Arc::new(c.clone()));
+        self.http2_max_concurrent_streams = http2_max_concurrent_streams;
+        self.http2_initial_stream_window_size = http2_initial_stream_window_size;
+        self.http2_initial_connection_window_size = http2_initial_connection_window_size;
+        self.drain_timeout = to_py_exception(Duration::try_from_secs_f64(drain_timeout))?;
+        self.workers = workers;
+        self.header_read_timeout = seconds(header_read_timeout)?;
+        self.keep_alive_timeout = seconds(keep_alive_timeout)?;
+        self.max_requests_per_connection = max_requests_per_connection;
+        self.request_timeout = seconds(request_timeout)?;
+        self.max_body_size = max_body_size;
+        self.trusted_proxies = TrustedProxies::parse(&trusted_proxies.unwrap_or_default())?;
+        self.compression = compression.then_some(Compression {
+            min_size: compression_min_size,
+        });
+        self.max_decompressed_size = max_decompressed_size;
         Ok(())
     }
 }
 
 impl HttpServer {
-    async fn run_server(&self) -> PyResult<()> {
-        let running = Arc::new(AtomicBool::new(true));
-        let r = running.clone();
-        let addr = self.addr;
+    // Serves HTTP/1.1 and HTTP/2 on the same listener; h2c is detected from the
+    // connection preface (prior knowledge).
+    fn connection_builder(&self) -> auto::Builder<TokioExecutor> {
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(self.header_read_timeout)
+            .http2()
+            .max_concurrent_streams(self.http2_max_concurrent_streams)
+            .initial_stream_window_size(self.http2_initial_stream_window_size)
+            .initial_connection_window_size(self.http2_initial_connection_window_size);
+        builder
+    }
+
+    fn bind_listeners(&self) -> std::io::Result<Vec<StdListener>> {
+        self.binds
+            .iter()
+            .map(|bind| StdListener::bind(bind, &self.binds))
+            .collect()
+    }
+
+    fn remove_socket_files(&self) {
+        for bind in &self.binds {
+            if let Bind::Unix { path, .. } = bind {
+                _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    /// Serves `listeners` in the current process until SIGINT or SIGTERM.
+    pub(crate) fn serve(&self, listeners: Vec<StdListener>, py: Python<'_>) -> PyResult<()> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        py.allow_threads(|| {
+            let result = runtime.block_on(async move {
+                let shutdown = async {
+                    shutdown::signal().await;
+                    None
+                };
+                self.run_server(listeners, shutdown).await
+            });
+            runtime.shutdown_timeout(SHUTDOWN_GRACE);
+            result
+        })?;
+
+        // The interpreter saw the same Ctrl+C that triggered the shutdown; consume it
+        // so `run()` returns normally instead of raising KeyboardInterrupt.
+        match py.check_signals() {
+            Err(err) if !err.is_instance_of::<PyKeyboardInterrupt>(py) => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    // `shutdown` resolves when the server should stop, optionally overriding the
+    // configured drain timeout.
+    async fn run_server(
+        &self,
+        listeners: Vec<StdListener>,
+        shutdown: impl Future<Output = Option<Duration>> + Send + 'static,
+    ) -> PyResult<()> {
+        let listeners = listeners
+            .into_iter()
+            .map(Listener::from_std)
+            .collect::<std::io::Result<Vec<_>>>()?;
         let channel_capacity = self.channel_capacity;
+        let drain_timeout = self.drain_timeout;
 
         let (request_sender, mut request_receiver) = channel::<ProcessRequest>(channel_capacity);
-        let (shutdown_tx, mut shutdown_rx) = channel::<()>(1);
+        let (stop_tx, mut stop_rx) = channel::<Option<Instant>>(1);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
 
-        ctrlc::set_handler(move || {
-            println!("\nReceived Ctrl+C! Shutting Down...");
-            r.store(false, Ordering::SeqCst);
-            let runtime = tokio::runtime::Runtime::new().unwrap();
-            runtime.block_on(shutdown_tx.send(())).unwrap();
-        })
-        .ok();
+        for listener in &listeners {
+            match listener.local_addr()? {
+                Address::Tcp(addr) if self.tls.is_some() => println!("Listening on https://{addr}"),
+                Address::Tcp(addr) => println!("Listening on http://{addr}"),
+                addr => println!("Listening on {addr}"),
+            }
+        }
 
-        let listener = TcpListener::bind(addr).await?;
-        println!("Listening on {}", addr);
+        // Once shutdown is requested the accept loop stops and every open connection
+        // is asked to finish its in-flight requests. Each of them holds a clone of
+        // `shutdown_rx`, so `closed()` resolves when the last one is gone.
+        tokio::spawn(async move {
+            let drain_timeout = shutdown.await.unwrap_or(drain_timeout);
+            // A timeout too large to represent means no deadline at all.
+            let deadline = Instant::now().checked_add(drain_timeout);
+            println!("\nShutting down gracefully...");
+            _ = shutdown_tx.send(true);
+            if within(deadline, shutdown_tx.closed()).await.is_err() {
+                println!("Drain timeout exceeded, closing remaining connections");
+            }
+            _ = stop_tx.send(deadline).await;
+        });
 
-        let routers = self.routers.clone();
-        let running_clone = running.clone();
-        let request_sender = request_sender.clone();
+        let context = Arc::new(ServerContext {
+            request_sender,
+            routers: self.routers.clone(),
+            app_data: self.app_data.clone(),
+            channel_capacity,
+            cors: self.cors_header.clone(),
+            request_timeout: self.request_timeout,
+            max_body_size: self.max_body_size,
+            trusted_proxies: self.trusted_proxies.clone(),
+            compression: self.compression,
+            max_decompressed_size: self.max_decompressed_size,
+        });
         let max_connections = self.max_connections.clone();
-        let app_data = self.app_data.clone();
-        let cors = self.cors_header.clone();
+        let builder = Arc::new(self.connection_builder());
+        let tls = self.tls.clone();
+        let keep_alive_timeout = self.keep_alive_timeout;
+        let handshake_timeout = self.header_read_timeout;
+        let max_requests = self.max_requests_per_connection;
 
-        tokio::spawn(async move {
-            while running_clone.load(Ordering::SeqCst) {
-                let permit = max_connections.clone().acquire_owned().await.unwrap();
-                let (stream, _) = listener.accept().await.unwrap();
-                let io = TokioIo::new(stream);
-                let request_sender = request_sender.clone();
-                let routers = routers.clone();
-                let app_data = app_data.clone();
-                let cors = cors.clone();
-
-                tokio::spawn(async move {
-                    let _permit = permit;
-                    let result = http1::Builder::new()
-                        .serve_connection(
+        for listener in listeners {
+            let context = context.clone();
+            let max_connections = max_connections.clone();
+            let builder = builder.clone();
+            let tls = tls.clone();
+            let mut shutdown_rx = shutdown_rx.clone();
+
+            tokio::spawn(async move {
+                loop {
+                    let permit = tokio::select! {
+                        permit = max_connections.clone().acquire_owned() => permit.unwrap(),
+                        _ = shutdown_rx.changed() => break,
+                    };
+                    let (stream, peer_addr) = tokio::select! {
+                        accepted = listener.accept() => match accepted {
+                            Ok(accepted) => accepted,
+                            Err(_) => continue,
+                        },
+                        _ = shutdown_rx.changed() => break,
+                    };
+                    let context = context.clone();
+                    let builder = builder.clone();
+                    let tls = tls.clone();
+                    let mut shutdown_rx = shutdown_rx.clone();
+
+                    tokio::spawn(async move {
+                        let _permit = permit;
+                        let stream = match (tls, stream) {
+                            (Some(acceptor), Stream::Tcp(stream)) => {
+                                // A handshake that never completes would hold its
+                                // connection slot forever.
+                                let handshake = acceptor.accept(stream);
+                                let accepted = match handshake_timeout {
+                                    Some(limit) => tokio::time::timeout(limit, handshake).await,
+                                    None => Ok(handshake.await),
+                                };
+                                match accepted {
+                                    Ok(Ok(stream)) => Stream::Tls(Box::new(stream)),
+                                    _ => return Ok(()),
+                                }
+                            }
+                            (_, stream) => stream,
+                        };
+                        let peer = Peer {
+                            addr: peer_addr,
+                            secure: matches!(stream, Stream::Tls(_)),
+                        };
+                        let state = ConnectionState::new(max_requests);
+                        let io = TokioIo::new(TrackedIo::new(stream, state.clone()));
+                        let connection = builder.serve_connection(
                             io,
-                            service_fn(move |req| {
-                                let request_sender = request_sender.clone();
-                                let routers = routers.clone();
-                                let app_data = app_data.clone();
-                                let cors = cors.clone();
-
-                                async move {
-                                    handle_request(
-                                        req,
-                                        request_sender,
-                                        routers,
-                                        app_data,
-                                        channel_capacity,
-                                        cors,
-                                    )
-                                    .await
+                            service_fn({
+                                let state = state.clone();
+                                move |req| {
+                                    let context = context.clone();
+                                    let guard = state.request();
+
+                                    async move {
+                                        let response = handle_request(req, context, peer).await;
+                                        drop(guard);
+                                        response
+                                    }
                                 }
                             }),
-                        )
-                        .await;
-                    to_py_exception(result)
-                });
-            }
-        });
+                        );
+                        tokio::pin!(connection);
+
+                        let result = tokio::select! {
+                            result = connection.as_mut() => result,
+                            _ = shutdown_rx.changed() => {
+                                connection.as_mut().graceful_shutdown();
+                                connection.await
+                            }
+                            _ = state.idle(keep_alive_timeout) => {
+                                // A half-sent request head keeps HTTP/1.1 from closing
+                                // gracefully, so give up after another idle period.
+                                connection.as_mut().graceful_shutdown();
+                                let grace = keep_alive_timeout.unwrap_or_default();
+                                tokio::time::timeout(grace, connection).await.unwrap_or(Ok(()))
+                            }
+                            _ = state.exhausted() => {
+                                connection.as_mut().graceful_shutdown();
+                                connection.await
+                            }
+                        };
+                        to_py_exception(result)
+                    });
+                }
+            });
+        }
+        drop(context);
+        drop(shutdown_rx);
 
-        handle_response(&mut shutdown_rx, &mut request_receiver).await;
+        handle_response(&mut stop_rx, &mut request_receiver, self.workers).await;
 
         Ok(())
     }
@@ -186,10 +524,19 @@ impl HttpServer {
 #[pymodule]
 fn oxapy(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<HttpServer>()?;
+    m.add_class::<ServerHandle>()?;
     m.add_class::<Router>()?;
     m.add_class::<Status>()?;
     m.add_class::<Response>()?;
+    m.add_class::<StreamingResponse>()?;
+    m.add_class::<EventStream>()?;
+    m.add_class::<FileResponse>()?;
+    m.add_class::<Event>()?;
     m.add_class::<Request>()?;
+    m.add_class::<Headers>()?;
+    m.add_class::<UploadFile>()?;
+    m.add_class::<RequestBody>()?;
+    m.add_class::<MultiDict>()?;
     m.add_class::<Cors>()?;
     m.add_function(wrap_pyfunction!(get, m)?)?;
     m.add_function(wrap_pyfunction!(post, m)?)?;
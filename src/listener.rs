@@ -0,0 +1,220 @@
+use std::{fmt, io, net::SocketAddr, path::PathBuf};
+
+use pyo3::{prelude::*, IntoPyObjectExt};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+use crate::stream::Stream;
+
+/// Pending connections the kernel queues before `accept`.
+const BACKLOG: i32 = 1024;
+
+/// An address accepted by `HttpServer(...)` and `HttpServer.bind(...)`: an
+/// `(ip, port)` tuple, or a filesystem path for a Unix domain socket.
+#[derive(FromPyObject)]
+pub enum BindAddr {
+    Tcp((String, u16)),
+    Unix(PathBuf),
+}
+
+#[derive(Clone, Debug)]
+pub enum Bind {
+    Tcp(SocketAddr),
+    Unix {
+        path: PathBuf,
+        mode: Option<u32>,
+        owner: Option<u32>,
+        group: Option<u32>,
+    },
+}
+
+impl Bind {
+    pub fn unix(path: PathBuf) -> Self {
+        Bind::Unix {
+            path,
+            mode: None,
+            owner: None,
+            group: None,
+        }
+    }
+}
+
+impl TryFrom<BindAddr> for Bind {
+    type Error = PyErr;
+
+    fn try_from(addr: BindAddr) -> PyResult<Self> {
+        match addr {
+            BindAddr::Tcp((ip, port)) => Ok(Bind::Tcp(SocketAddr::new(ip.parse()?, port))),
+            BindAddr::Unix(path) => Ok(Bind::unix(path)),
+        }
+    }
+}
+
+/// The address a listener ended up bound to, e.g. with the OS-chosen port for port 0.
+#[derive(Clone, Debug)]
+pub enum Address {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Tcp(addr) => write!(f, "{addr}"),
+            Address::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl<'py> IntoPyObject<'py> for Address {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        match self {
+            Address::Tcp(addr) => (addr.ip().to_string(), addr.port()).into_bound_py_any(py),
+            Address::Unix(path) => path.into_bound_py_any(py),
+        }
+    }
+}
+
+/// A bound but not yet registered listener. Binding happens before the tokio
+/// runtime exists so the sockets can be inherited by forked worker processes.
+pub enum StdListener {
+    Tcp(std::net::TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener, PathBuf),
+}
+
+impl StdListener {
+    /// Binds `bind`, one of `binds`, the addresses the server listens on.
+    pub fn bind(bind: &Bind, binds: &[Bind]) -> io::Result<Self> {
+        match bind {
+            Bind::Tcp(addr) => {
+                // An IPv6 socket also takes IPv4 connections by default, which
+                // would clash with an IPv4 listener on the same port.
+                let only_v6 = addr.is_ipv6()
+                    && binds.iter().any(|other| {
+                        matches!(other, Bind::Tcp(other) if other.is_ipv4() && other.port() == addr.port())
+                    });
+                Ok(StdListener::Tcp(bind_tcp(*addr, only_v6)?))
+            }
+            #[cfg(unix)]
+            Bind::Unix {
+                path,
+                mode,
+                owner,
+                group,
+            } => {
+                use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+                // A socket file left behind by a previous run would make bind fail,
+                // but one a running server still accepts on must be left alone.
+                if let Ok(metadata) = std::fs::symlink_metadata(path) {
+                    if metadata.file_type().is_socket() {
+                        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                            return Err(io::Error::new(
+                                io::ErrorKind::AddrInUse,
+                                format!("{} is in use by another server", path.display()),
+                            ));
+                        }
+                        std::fs::remove_file(path)?;
+                    }
+                }
+
+                let listener = std::os::unix::net::UnixListener::bind(path)?;
+                if let Some(mode) = mode {
+                    std::fs::set_permissions(path, std::fs::Permissions::from_mode(*mode))?;
+                }
+                if owner.is_some() || group.is_some() {
+                    std::os::unix::fs::chown(path, *owner, *group)?;
+                }
+                Ok(StdListener::Unix(listener, path.clone()))
+            }
+            #[cfg(not(unix))]
+            Bind::Unix { .. } => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unix domain sockets are not supported on this platform",
+            )),
+        }
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            StdListener::Tcp(listener) => Ok(StdListener::Tcp(listener.try_clone()?)),
+            #[cfg(unix)]
+            StdListener::Unix(listener, path) => {
+                Ok(StdListener::Unix(listener.try_clone()?, path.clone()))
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<Address> {
+        match self {
+            StdListener::Tcp(listener) => Ok(Address::Tcp(listener.local_addr()?)),
+            #[cfg(unix)]
+            StdListener::Unix(_, path) => Ok(Address::Unix(path.clone())),
+        }
+    }
+}
+
+/// Binds like `std::net::TcpListener::bind`. With `only_v6` an IPv6 socket
+/// accepts IPv6 only, so `("::", port)` and `("0.0.0.0", port)` can be bound
+/// together.
+fn bind_tcp(addr: SocketAddr, only_v6: bool) -> io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if only_v6 {
+        socket.set_only_v6(true)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(BACKLOG)?;
+    Ok(socket.into())
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    /// Registers `listener` with the current tokio runtime.
+    pub fn from_std(listener: StdListener) -> io::Result<Self> {
+        match listener {
+            StdListener::Tcp(listener) => {
+                listener.set_nonblocking(true)?;
+                Ok(Listener::Tcp(TcpListener::from_std(listener)?))
+            }
+            #[cfg(unix)]
+            StdListener::Unix(listener, path) => {
+                listener.set_nonblocking(true)?;
+                Ok(Listener::Unix(UnixListener::from_std(listener)?, path))
+            }
+        }
+    }
+
+    /// Accepts a connection along with the peer address, which Unix sockets lack.
+    pub async fn accept(&self) -> io::Result<(Stream, Option<SocketAddr>)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, addr) = listener.accept().await?;
+                Ok((Stream::Tcp(stream), Some(addr)))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => Ok((Stream::Unix(listener.accept().await?.0), None)),
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<Address> {
+        match self {
+            Listener::Tcp(listener) => Ok(Address::Tcp(listener.local_addr()?)),
+            #[cfg(unix)]
+            Listener::Unix(_, path) => Ok(Address::Unix(path.clone())),
+        }
+    }
+}
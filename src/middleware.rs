@@ -2,18 +2,20 @@ use std::sync::Arc;
 
 use pyo3::{ffi::c_str, prelude::*, types::PyDict, Py, PyAny, PyResult, Python};
 
-use crate::request::Request;
+use crate::{coroutine, request::Request};
 
 #[derive(Clone, Debug)]
 pub struct Middleware {
     handler: Arc<Py<PyAny>>,
+    is_async: bool,
 }
 
 impl Middleware {
-    pub fn new(handler: Py<PyAny>) -> Self {
-        Self {
+    pub fn new(handler: Py<PyAny>, py: Python<'_>) -> PyResult<Self> {
+        Ok(Self {
+            is_async: coroutine::is_coroutine_function(handler.bind(py))?,
             handler: Arc::new(handler),
-        }
+        })
     }
 }
 
@@ -29,7 +31,7 @@ impl MiddlewareChain {
     pub fn execute<'py>(
         &self,
         py: Python<'py>,
-        request: &Request,
+        request: &Py<Request>,
         route_handler: &Py<PyAny>,
         kwargs: Bound<'py, PyDict>,
     ) -> PyResult<Py<PyAny>> {
@@ -40,7 +42,7 @@ impl MiddlewareChain {
     fn build_middleware_chain(
         &self,
         py: Python<'_>,
-        request: &Request,
+        request: &Py<Request>,
         route_handler: &Py<PyAny>,
         index: usize,
     ) -> PyResult<Py<PyAny>> {
@@ -48,10 +50,13 @@ impl MiddlewareChain {
             return Ok(route_handler.clone_ref(py));
         }
         let middleware = &self.middlewares[index];
-        let next = self.build_middleware_chain(py, request, route_handler, index + 1)?;
+        let mut next = self.build_middleware_chain(py, request, route_handler, index + 1)?;
+        if middleware.is_async {
+            next = coroutine::awaitable_next(py, next)?;
+        }
         let globals = PyDict::new(py);
         globals.set_item("middleware", middleware.handler.clone_ref(py))?;
-        globals.set_item("request", request.clone())?;
+        globals.set_item("request", request.clone_ref(py))?;
         globals.set_item("next_fn", next)?;
         let wrapper_code =
             c_str!(r#"lambda **kwargs: middleware(request=request, next=next_fn, **kwargs)"#);
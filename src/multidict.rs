@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use pyo3::{
+    exceptions::PyKeyError,
+    prelude::*,
+    types::{PyDict, PyIterator, PyList},
+    IntoPyObjectExt,
+};
+
+/// A read-only mapping that keeps every value of repeated keys, in the order
+/// received. Indexing and `get` return the last value, like a plain dict built
+/// from the same pairs would; `getlist` returns all of them.
+#[pyclass]
+pub struct MultiDict {
+    items: Vec<(String, Py<PyAny>)>,
+    /// Positions in `items` of every key, in order.
+    index: HashMap<String, Vec<usize>>,
+}
+
+impl MultiDict {
+    pub fn from_pairs<'py, I, V>(py: Python<'py>, pairs: I) -> PyResult<Self>
+    where
+        I: IntoIterator<Item = (String, V)>,
+        V: IntoPyObject<'py>,
+    {
+        let items = pairs
+            .into_iter()
+            .map(|(key, value)| Ok((key, value.into_py_any(py)?)))
+            .collect::<PyResult<_>>()?;
+        Ok(Self::with_items(items))
+    }
+
+    fn with_items(items: Vec<(String, Py<PyAny>)>) -> Self {
+        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+        for (position, (key, _)) in items.iter().enumerate() {
+            index.entry(key.clone()).or_default().push(position);
+        }
+        Self { items, index }
+    }
+
+    fn positions(&self, key: &str) -> &[usize] {
+        self.index.get(key).map_or(&[], Vec::as_slice)
+    }
+
+    fn last(&self, key: &str) -> Option<&Py<PyAny>> {
+        let position = *self.positions(key).last()?;
+        Some(&self.items[position].1)
+    }
+
+    /// Every key once, in the order it first appeared.
+    fn keys_unique(&self) -> Vec<&str> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(position, (key, _))| self.positions(key).first() == Some(position))
+            .map(|(_, (key, _))| key.as_str())
+            .collect()
+    }
+}
+
+#[pymethods]
+impl MultiDict {
+    #[new]
+    #[pyo3(signature=(items=None))]
+    fn new(items: Option<Vec<(String, Py<PyAny>)>>) -> Self {
+        Self::with_items(items.unwrap_or_default())
+    }
+
+    #[pyo3(signature=(key, default=None))]
+    fn get(&self, key: &str, default: Option<Py<PyAny>>, py: Python<'_>) -> Option<Py<PyAny>> {
+        self.last(key).map(|value| value.clone_ref(py)).or(default)
+    }
+
+    fn getlist(&self, key: &str, py: Python<'_>) -> Vec<Py<PyAny>> {
+        self.positions(key)
+            .iter()
+            .map(|&position| self.items[position].1.clone_ref(py))
+            .collect()
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.keys_unique()
+    }
+
+    /// The last value of every key.
+    fn values(&self, py: Python<'_>) -> Vec<Py<PyAny>> {
+        self.keys_unique()
+            .into_iter()
+            .filter_map(|key| self.last(key).map(|value| value.clone_ref(py)))
+            .collect()
+    }
+
+    /// Every `(key, value)` pair, including repeated keys.
+    fn items(&self, py: Python<'_>) -> Vec<(String, Py<PyAny>)> {
+        self.items
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone_ref(py)))
+            .collect()
+    }
+
+    /// A plain dict holding the last value of every key.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        for (key, value) in &self.items {
+            dict.set_item(key, value)?;
+        }
+        Ok(dict)
+    }
+
+    fn __getitem__(&self, key: &str, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        self.last(key)
+            .map(|value| value.clone_ref(py))
+            .ok_or_else(|| PyKeyError::new_err(key.to_string()))
+    }
+
+    fn __contains__(&self, key: &str) -> bool {
+        self.index.contains_key(key)
+    }
+
+    fn __len__(&self) -> usize {
+        self.index.len()
+    }
+
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        PyList::new(py, self.keys_unique())?.try_iter()
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        let items = PyList::new(py, self.items(py))?;
+        Ok(format!("MultiDict({})", items.repr()?))
+    }
+}
@@ -0,0 +1,108 @@
+use std::{convert::Infallible, path::PathBuf};
+
+use futures_util::FutureExt;
+use hyper::body::Bytes;
+use pyo3::{prelude::*, types::PyBytes};
+
+/// A file received in a `multipart/form-data` body.
+#[derive(Clone, Debug)]
+#[pyclass]
+pub struct UploadFile {
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub filename: String,
+    #[pyo3(get)]
+    pub content_type: Option<String>,
+    pub content: Bytes,
+}
+
+#[pymethods]
+impl UploadFile {
+    #[getter]
+    fn size(&self) -> usize {
+        self.content.len()
+    }
+
+    /// The file content as bytes.
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.content)
+    }
+
+    fn read<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        self.data(py)
+    }
+
+    /// A `tempfile.SpooledTemporaryFile` holding the content, rewound to the
+    /// start, for libraries that expect a file object.
+    fn file<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let file = py
+            .import("tempfile")?
+            .call_method0("SpooledTemporaryFile")?;
+        file.call_method1("write", (self.data(py),))?;
+        file.call_method1("seek", (0,))?;
+        Ok(file)
+    }
+
+    fn save(&self, path: PathBuf, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| std::fs::write(path, &self.content))?;
+        Ok(())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "UploadFile(name={:?}, filename={:?}, size={})",
+            self.name,
+            self.filename,
+            self.content.len()
+        )
+    }
+}
+
+/// Text fields and files of a `multipart/form-data` body, in the order received.
+#[derive(Clone, Debug, Default)]
+pub struct FormData {
+    pub fields: Vec<(String, String)>,
+    pub files: Vec<UploadFile>,
+}
+
+pub fn is_multipart(content_type: &str) -> bool {
+    content_type
+        .split(';')
+        .next()
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("multipart/form-data"))
+}
+
+/// Parses an already collected `multipart/form-data` body. Parts with a
+/// filename become files, every other part is read as a UTF-8 text field.
+pub fn parse(content_type: &str, body: Bytes) -> multer::Result<FormData> {
+    // The whole body is available up front, so parsing never has to wait.
+    read(content_type, body)
+        .now_or_never()
+        .unwrap_or(Err(multer::Error::IncompleteStream))
+}
+
+async fn read(content_type: &str, body: Bytes) -> multer::Result<FormData> {
+    let boundary = multer::parse_boundary(content_type)?;
+    let stream = futures_util::stream::once(async move { Ok::<_, Infallible>(body) });
+    let mut multipart = multer::Multipart::new(stream, boundary);
+
+    let mut form = FormData::default();
+    while let Some(field) = multipart.next_field().await? {
+        let name = field.name().unwrap_or_default().to_string();
+        match field.file_name().map(str::to_string) {
+            Some(filename) => {
+                let content_type = field.content_type().map(ToString::to_string);
+                form.files.push(UploadFile {
+                    name,
+                    filename,
+                    content_type,
+                    content: field.bytes().await?,
+                });
+            }
+            None => form.fields.push((name, field.text().await?)),
+        }
+    }
+    Ok(form)
+}
@@ -0,0 +1,375 @@
+use pyo3::{
+    exceptions::{PyTypeError, PyValueError},
+    ffi::c_str,
+    prelude::*,
+    sync::GILOnceCell,
+    types::{PyDict, PyList},
+};
+
+use crate::{response::Response, status::Status};
+
+static HELPERS: GILOnceCell<Py<PyModule>> = GILOnceCell::new();
+
+fn helpers(py: Python<'_>) -> PyResult<&Bound<'_, PyModule>> {
+    let module = HELPERS.get_or_try_init(py, || {
+        PyModule::from_code(
+            py,
+            c_str!(
+                r#"
+import dataclasses
+import datetime
+import decimal
+import enum
+import inspect
+import re
+import sys
+import types
+import typing
+import uuid
+
+TRUE = {"true", "1", "yes", "on"}
+FALSE = {"false", "0", "no", "off"}
+
+# `X | Y` unions and `typing.is_typeddict` are new in Python 3.10.
+UNION_TYPES = (typing.Union, getattr(types, "UnionType", typing.Union))
+is_typeddict = getattr(
+    typing,
+    "is_typeddict",
+    lambda annotation: isinstance(annotation, type)
+    and issubclass(annotation, dict)
+    and hasattr(annotation, "__total__"),
+)
+
+
+def annotations(handler):
+    try:
+        signature = inspect.signature(handler, eval_str=True)
+        hints = {}
+    except TypeError:
+        # `eval_str` is new in Python 3.10; resolve string annotations here.
+        signature = inspect.signature(handler)
+        try:
+            hints = typing.get_type_hints(handler)
+        except Exception:
+            hints = {}
+    except Exception:
+        signature = inspect.signature(handler)
+        hints = {}
+    annotations = {
+        name: hints.get(name, param.annotation) for name, param in signature.parameters.items()
+    }
+    return {
+        name: annotation
+        for name, annotation in annotations.items()
+        if annotation not in (inspect.Parameter.empty, str, typing.Any)
+    }
+
+
+def is_list(annotation):
+    return typing.get_origin(annotation) in (list, tuple, set) or annotation in (list, tuple, set)
+
+
+def convert_query(values, annotation):
+    if is_list(annotation):
+        args = typing.get_args(annotation)
+        kind = typing.get_origin(annotation) or annotation
+        return kind(convert(value, args[0]) if args else value for value in values)
+    return convert(values[-1], annotation)
+
+
+def convert(value, annotation):
+    if annotation in (inspect.Parameter.empty, str, typing.Any):
+        return value
+    origin = typing.get_origin(annotation)
+    if origin in UNION_TYPES:
+        errors = []
+        for option in typing.get_args(annotation):
+            if option is type(None):
+                continue
+            try:
+                return convert(value, option)
+            except (TypeError, ValueError) as error:
+                errors.append(str(error))
+        raise ValueError("; ".join(errors))
+    if origin is typing.Literal:
+        for option in typing.get_args(annotation):
+            if str(option) == value:
+                return option
+        raise ValueError(f"expected one of {list(typing.get_args(annotation))}")
+    if annotation is bool:
+        if value.lower() in TRUE:
+            return True
+        if value.lower() in FALSE:
+            return False
+        raise ValueError("value is not a valid boolean")
+    if isinstance(annotation, type) and issubclass(annotation, enum.Enum):
+        for member in annotation:
+            if str(member.value) == value or member.name == value:
+                return member
+        raise ValueError(f"expected one of {[str(member.value) for member in annotation]}")
+    if annotation in (datetime.date, datetime.datetime, datetime.time):
+        return annotation.fromisoformat(value)
+    if annotation is decimal.Decimal:
+        try:
+            return decimal.Decimal(value)
+        except decimal.InvalidOperation:
+            raise ValueError("value is not a valid decimal") from None
+    if annotation in (int, float, uuid.UUID):
+        try:
+            return annotation(value)
+        except ValueError:
+            raise ValueError(f"value is not a valid {annotation.__name__}") from None
+    if callable(annotation):
+        return annotation(value)
+    return value
+
+
+class ValidationFailed(Exception):
+    def __init__(self, errors):
+        super().__init__(errors)
+        self.errors = errors
+
+
+def validate_body(value, annotation, coerce):
+    if hasattr(value, "to_dict") and annotation is not type(value):
+        value = value.to_dict()
+    errors = []
+    result = check(value, annotation, ["body"], errors, coerce)
+    if errors:
+        raise ValidationFailed(errors)
+    return result
+
+
+def error(errors, loc, msg, value):
+    errors.append({"loc": list(loc), "msg": msg, "input": value})
+
+
+def check(value, annotation, loc, errors, coerce):
+    if annotation in (inspect.Parameter.empty, typing.Any, object):
+        return value
+    if hasattr(annotation, "model_validate"):
+        return check_pydantic(value, annotation, loc, errors, annotation.model_validate)
+    if hasattr(annotation, "parse_obj") and hasattr(annotation, "__fields__"):
+        return check_pydantic(value, annotation, loc, errors, annotation.parse_obj)
+    msgspec = sys.modules.get("msgspec")
+    if msgspec and isinstance(annotation, type) and issubclass(annotation, msgspec.Struct):
+        return check_msgspec(value, annotation, loc, errors, msgspec)
+    if dataclasses.is_dataclass(annotation):
+        return check_dataclass(value, annotation, loc, errors, coerce)
+    if is_typeddict(annotation):
+        return check_typeddict(value, annotation, loc, errors, coerce)
+
+    origin = typing.get_origin(annotation)
+    args = typing.get_args(annotation)
+    if origin in UNION_TYPES:
+        if value is None and type(None) in args:
+            return None
+        options = [option for option in args if option is not type(None)]
+        if len(options) == 1:
+            return check(value, options[0], loc, errors, coerce)
+        for option in options:
+            attempt = []
+            result = check(value, option, loc, attempt, coerce)
+            if not attempt:
+                return result
+        return error(errors, loc, f"value does not match {annotation}", value)
+    if origin is typing.Literal:
+        if value in args:
+            return value
+        return error(errors, loc, f"expected one of {list(args)}", value)
+    if origin in (list, set, tuple) or annotation in (list, set, tuple):
+        if not isinstance(value, list):
+            return error(errors, loc, "value is not a valid list", value)
+        if not args:
+            return (origin or annotation)(value)
+        return (origin or annotation)(
+            check(item, args[0], [*loc, index], errors, coerce) for index, item in enumerate(value)
+        )
+    if origin is dict or annotation is dict:
+        if not isinstance(value, dict):
+            return error(errors, loc, "value is not a valid object", value)
+        if len(args) != 2:
+            return value
+        return {key: check(item, args[1], [*loc, key], errors, coerce) for key, item in value.items()}
+    if coerce and isinstance(value, str) and annotation in (bool, int, float):
+        try:
+            return convert(value, annotation)
+        except ValueError as exc:
+            return error(errors, loc, str(exc), value)
+    if annotation is bool:
+        if isinstance(value, bool):
+            return value
+        return error(errors, loc, "value is not a valid boolean", value)
+    if annotation is int:
+        if isinstance(value, int) and not isinstance(value, bool):
+            return value
+        return error(errors, loc, "value is not a valid int", value)
+    if annotation is float:
+        if isinstance(value, (int, float)) and not isinstance(value, bool):
+            return float(value)
+        return error(errors, loc, "value is not a valid float", value)
+    if annotation in (str, bytes):
+        if isinstance(value, annotation):
+            return value
+        return error(errors, loc, f"value is not a valid {annotation.__name__}", value)
+    if isinstance(annotation, type) and isinstance(value, annotation):
+        return value
+    if isinstance(value, str) or isinstance(annotation, type) and issubclass(annotation, enum.Enum):
+        try:
+            return convert(value, annotation) if isinstance(value, str) else annotation(value)
+        except (TypeError, ValueError) as exc:
+            return error(errors, loc, str(exc), value)
+    return error(errors, loc, f"value is not a valid {getattr(annotation, '__name__', annotation)}", value)
+
+
+def fields_of(value, annotation, loc, errors):
+    if not isinstance(value, dict):
+        error(errors, loc, "value is not a valid object", value)
+        return None
+    try:
+        return typing.get_type_hints(annotation)
+    except Exception:
+        return dict(getattr(annotation, "__annotations__", {}))
+
+
+def check_dataclass(value, annotation, loc, errors, coerce):
+    hints = fields_of(value, annotation, loc, errors)
+    if hints is None:
+        return None
+    count = len(errors)
+    kwargs = {}
+    for field in dataclasses.fields(annotation):
+        if not field.init:
+            continue
+        if field.name in value:
+            hint = hints.get(field.name, typing.Any)
+            kwargs[field.name] = check(value[field.name], hint, [*loc, field.name], errors, coerce)
+        elif field.default is dataclasses.MISSING and field.default_factory is dataclasses.MISSING:
+            error(errors, [*loc, field.name], "field required", None)
+    if len(errors) > count:
+        return None
+    return annotation(**kwargs)
+
+
+def check_typeddict(value, annotation, loc, errors, coerce):
+    hints = fields_of(value, annotation, loc, errors)
+    if hints is None:
+        return None
+    result = dict(value)
+    for name, hint in hints.items():
+        if name in value:
+            result[name] = check(value[name], hint, [*loc, name], errors, coerce)
+        elif name in getattr(annotation, "__required_keys__", hints if annotation.__total__ else ()):
+            error(errors, [*loc, name], "field required", None)
+    return result
+
+
+def check_pydantic(value, annotation, loc, errors, validate):
+    try:
+        return validate(value)
+    except Exception as exc:
+        if not callable(getattr(exc, "errors", None)):
+            raise
+        for item in exc.errors():
+            error(errors, [*loc, *item.get("loc", ())], item.get("msg", str(exc)), item.get("input"))
+
+
+def check_msgspec(value, annotation, loc, errors, msgspec):
+    try:
+        return msgspec.convert(value, annotation)
+    except msgspec.ValidationError as exc:
+        message, _, path = str(exc).partition(" - at `$")
+        keys = [
+            int(index) if index else key
+            for key, index in re.findall(r"\.(\w+)|\[(\d+)\]", path.rstrip("`"))
+        ]
+        error(errors, [*loc, *keys], message, value)
+"#
+            ),
+            c_str!("oxapy_params.py"),
+            c_str!("oxapy_params"),
+        )
+        .map(Into::into)
+    })?;
+    Ok(module.bind(py))
+}
+
+/// The annotations of `handler`'s parameters that call for a conversion;
+/// `str`, `Any` and missing annotations are left out.
+pub fn annotations<'py>(handler: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyDict>> {
+    Ok(helpers(handler.py())?
+        .call_method1("annotations", (handler,))?
+        .downcast_into()?)
+}
+
+/// Converts a path parameter to `annotation`.
+pub fn convert<'py>(value: &str, annotation: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>> {
+    helpers(annotation.py())?.call_method1("convert", (value, annotation))
+}
+
+/// Converts the values of a repeated query parameter: list annotations take
+/// all of them, anything else the last one.
+pub fn convert_query<'py>(
+    values: Vec<String>,
+    annotation: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    helpers(annotation.py())?.call_method1("convert_query", (values, annotation))
+}
+
+/// Builds the body of the parameter named by `data=` from its annotation:
+/// dataclasses, `TypedDict`s, msgspec `Struct`s, pydantic models and plain
+/// types are supported. Every failing field is added to `errors`. With
+/// `coerce`, as for form bodies, strings are converted like query parameters.
+pub fn validate_body<'py>(
+    value: Bound<'py, PyAny>,
+    annotation: &Bound<'py, PyAny>,
+    coerce: bool,
+    errors: &Bound<'py, PyList>,
+) -> PyResult<Option<Bound<'py, PyAny>>> {
+    let py = annotation.py();
+    let helpers = helpers(py)?;
+    match helpers.call_method1("validate_body", (value, annotation, coerce)) {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.is_instance(py, &helpers.getattr("ValidationFailed")?) => {
+            for error in err.value(py).getattr("errors")?.try_iter()? {
+                errors.append(error?)?;
+            }
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Records a value at `loc` that failed to convert. Only `ValueError` and
+/// `TypeError` count as conversion failures; anything else is re-raised so
+/// bugs in custom converters still surface as 500s.
+pub fn push_error(
+    errors: &Bound<'_, PyList>,
+    err: PyErr,
+    loc: &[&str],
+    input: Bound<'_, PyAny>,
+) -> PyResult<()> {
+    let py = errors.py();
+    if !err.is_instance_of::<PyValueError>(py) && !err.is_instance_of::<PyTypeError>(py) {
+        return Err(err);
+    }
+    let item = PyDict::new(py);
+    item.set_item("loc", loc)?;
+    item.set_item("msg", err.value(py).to_string())?;
+    item.set_item("input", input)?;
+    errors.append(item)
+}
+
+/// A 422 response listing every parameter that failed to convert, as
+/// `{"detail": [{"loc": [...], "msg": ..., "input": ...}]}`.
+pub fn error_response(errors: Bound<'_, PyList>) -> PyResult<Response> {
+    let py = errors.py();
+    let body = PyDict::new(py);
+    body.set_item("detail", errors)?;
+    Response::new(
+        Status::UNPROCESSABLE_ENTITY.into_pyobject(py)?.borrow(),
+        body.into_any().unbind(),
+        "application/json".to_string(),
+        py,
+    )
+}
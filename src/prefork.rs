@@ -0,0 +1,182 @@
+#[cfg(unix)]
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use pyo3::prelude::*;
+
+use crate::{listener::StdListener, HttpServer};
+
+#[cfg(not(unix))]
+pub fn run(
+    _server: &HttpServer,
+    _listeners: Vec<StdListener>,
+    _processes: usize,
+    _py: Python<'_>,
+) -> PyResult<()> {
+    Err(pyo3::exceptions::PyNotImplementedError::new_err(
+        "processes > 1 is only supported on Unix",
+    ))
+}
+
+/// A worker that crashes sooner than this after being forked is failing at startup.
+#[cfg(unix)]
+const MIN_UPTIME: Duration = Duration::from_secs(2);
+
+/// How long to wait before replacing a worker that failed at startup.
+#[cfg(unix)]
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Startup failures in a row after which the supervisor gives up.
+#[cfg(unix)]
+const MAX_STARTUP_FAILURES: u32 = 5;
+
+/// Forks `processes` workers that share the already bound `listeners` and
+/// supervises them: crashed workers are replaced, and SIGINT/SIGTERM received by
+/// the supervisor is forwarded so every worker drains and exits. Workers that
+/// keep crashing right after starting are restarted with a delay, and after
+/// `MAX_STARTUP_FAILURES` in a row the whole server is stopped.
+#[cfg(unix)]
+pub fn run(
+    server: &HttpServer,
+    listeners: Vec<StdListener>,
+    processes: usize,
+    py: Python<'_>,
+) -> PyResult<()> {
+    use std::{mem::MaybeUninit, ptr};
+
+    // Signals are blocked before forking so that none is lost between fork and
+    // `sigwait`; workers restore the original mask straight away.
+    let (signals, original_mask) = unsafe {
+        let mut signals = MaybeUninit::<libc::sigset_t>::uninit();
+        libc::sigemptyset(signals.as_mut_ptr());
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGCHLD] {
+            libc::sigaddset(signals.as_mut_ptr(), signal);
+        }
+        let signals = signals.assume_init();
+        let mut original_mask = MaybeUninit::<libc::sigset_t>::uninit();
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, original_mask.as_mut_ptr());
+        (signals, original_mask.assume_init())
+    };
+
+    let supervise = || -> PyResult<()> {
+        let mut workers = HashMap::new();
+        for _ in 0..processes {
+            let pid = spawn_worker(server, &listeners, &original_mask, py)?;
+            workers.insert(pid, Instant::now());
+        }
+
+        let mut stopping = false;
+        let mut failed = false;
+        let mut startup_failures = 0;
+        while !workers.is_empty() {
+            let mut signal = 0;
+            py.allow_threads(|| unsafe { libc::sigwait(&signals, &mut signal) });
+
+            if signal != libc::SIGCHLD {
+                if !stopping {
+                    println!("\nStopping {} workers...", workers.len());
+                }
+                stopping = true;
+                stop(&workers);
+                continue;
+            }
+
+            loop {
+                let mut status = 0;
+                let pid = unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) };
+                if pid <= 0 {
+                    break;
+                }
+                let Some(started) = workers.remove(&pid) else {
+                    continue;
+                };
+
+                let crashed = libc::WIFSIGNALED(status)
+                    || (libc::WIFEXITED(status) && libc::WEXITSTATUS(status) != 0);
+                if !crashed || stopping {
+                    continue;
+                }
+                if started.elapsed() >= MIN_UPTIME {
+                    startup_failures = 0;
+                    println!("Worker {pid} exited unexpectedly, restarting");
+                } else {
+                    startup_failures += 1;
+                    if startup_failures >= MAX_STARTUP_FAILURES {
+                        println!("Workers keep crashing at startup, stopping");
+                        stopping = true;
+                        failed = true;
+                        stop(&workers);
+                        continue;
+                    }
+                    println!(
+                        "Worker {pid} crashed at startup, restarting in {}s",
+                        RESTART_DELAY.as_secs_f64()
+                    );
+                    // Signals stay blocked meanwhile and are handled afterwards.
+                    py.allow_threads(|| std::thread::sleep(RESTART_DELAY));
+                }
+                let pid = spawn_worker(server, &listeners, &original_mask, py)?;
+                workers.insert(pid, Instant::now());
+            }
+        }
+        match failed {
+            true => Err(pyo3::exceptions::PyRuntimeError::new_err(
+                "worker processes keep crashing at startup",
+            )),
+            false => Ok(()),
+        }
+    };
+
+    let result = supervise();
+    unsafe { libc::pthread_sigmask(libc::SIG_SETMASK, &original_mask, ptr::null_mut()) };
+    result
+}
+
+/// Asks every worker to drain and exit.
+#[cfg(unix)]
+fn stop(workers: &HashMap<libc::pid_t, Instant>) {
+    for pid in workers.keys() {
+        unsafe { libc::kill(*pid, libc::SIGTERM) };
+    }
+}
+
+/// Forks through `os.fork()` so the interpreter runs its fork hooks. The child
+/// serves until it is told to stop and then exits without returning to Python.
+#[cfg(unix)]
+fn spawn_worker(
+    server: &HttpServer,
+    listeners: &[StdListener],
+    original_mask: &libc::sigset_t,
+    py: Python<'_>,
+) -> PyResult<libc::pid_t> {
+    let pid: libc::pid_t = py.import("os")?.call_method0("fork")?.extract()?;
+    if pid != 0 {
+        return Ok(pid);
+    }
+
+    unsafe { libc::pthread_sigmask(libc::SIG_SETMASK, original_mask, std::ptr::null_mut()) };
+
+    let listeners = listeners
+        .iter()
+        .map(StdListener::try_clone)
+        .collect::<std::io::Result<Vec<_>>>();
+    let code = match listeners
+        .map_err(PyErr::from)
+        .and_then(|listeners| server.serve(listeners, py))
+    {
+        Ok(()) => 0,
+        Err(err) => {
+            err.print(py);
+            1
+        }
+    };
+
+    if let Ok(sys) = py.import("sys") {
+        for stream in ["stdout", "stderr"] {
+            _ = sys.getattr(stream).and_then(|s| s.call_method0("flush"));
+        }
+    }
+    unsafe { libc::_exit(code) }
+}
@@ -0,0 +1,318 @@
+use std::net::{IpAddr, SocketAddr};
+
+use hyper::HeaderMap;
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+/// The connection a request arrived on.
+#[derive(Clone, Copy, Debug)]
+pub struct Peer {
+    /// `None` for Unix domain sockets.
+    pub addr: Option<SocketAddr>,
+    pub secure: bool,
+}
+
+/// Where a request really came from once trusted proxy headers are applied.
+#[derive(Debug)]
+pub struct Client {
+    pub addr: Option<SocketAddr>,
+    pub scheme: Option<String>,
+    pub host: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Network {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    fn parse(network: &str) -> Option<Self> {
+        let (addr, prefix) = match network.split_once('/') {
+            Some((addr, prefix)) => (addr.parse().ok()?, Some(prefix.parse().ok()?)),
+            None => (network.parse().ok()?, None),
+        };
+        let bits = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = prefix.unwrap_or(bits);
+        (prefix <= bits).then_some(Self { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        let mask = |bits: u32| {
+            u128::MAX
+                .checked_shl(bits - self.prefix as u32)
+                .unwrap_or(0)
+        };
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = mask(32) as u32;
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = mask(128);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Proxies whose `Forwarded`/`X-Forwarded-*` headers are believed. Headers from
+/// any other peer are ignored, since clients can send them too.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    networks: Vec<Network>,
+    unix: bool,
+}
+
+impl TrustedProxies {
+    /// Accepts IP addresses, CIDR ranges and `"unix"` for Unix socket peers.
+    pub fn parse(proxies: &[String]) -> PyResult<Self> {
+        let mut trusted = Self::default();
+        for proxy in proxies {
+            if proxy == "unix" {
+                trusted.unix = true;
+                continue;
+            }
+            let network = Network::parse(proxy).ok_or_else(|| {
+                PyValueError::new_err(format!("Invalid trusted proxy: {proxy:?}"))
+            })?;
+            trusted.networks.push(network);
+        }
+        Ok(trusted)
+    }
+
+    fn trusts(&self, peer: Option<SocketAddr>) -> bool {
+        match peer {
+            Some(addr) => self.networks.iter().any(|net| net.contains(addr.ip())),
+            None => self.unix,
+        }
+    }
+
+    /// Walks the forwarding chain from the closest hop outwards and stops at the
+    /// first address that is not a trusted proxy: that is the client.
+    pub fn resolve(&self, peer: Option<SocketAddr>, headers: &HeaderMap) -> Client {
+        let mut client = Client {
+            addr: peer,
+            scheme: None,
+            host: None,
+        };
+        if !self.trusts(peer) {
+            return client;
+        }
+
+        let forwarded = forwarded_elements(headers);
+        let hops: Vec<String> = match forwarded.is_empty() {
+            true => header_list(headers, "x-forwarded-for"),
+            false => forwarded.iter().filter_map(|e| param(e, "for")).collect(),
+        };
+        for hop in hops.iter().rev() {
+            let Some(addr) = parse_node(hop) else {
+                break;
+            };
+            client.addr = Some(addr);
+            if !self.trusts(Some(addr)) {
+                break;
+            }
+        }
+
+        let last = |name: &str| forwarded.iter().rev().find_map(|e| param(e, name));
+        client.scheme = last("proto")
+            .or_else(|| header_list(headers, "x-forwarded-proto").pop())
+            .map(|scheme| scheme.to_ascii_lowercase());
+        client.host = last("host").or_else(|| header_list(headers, "x-forwarded-host").pop());
+        client
+    }
+}
+
+fn header_list(headers: &HeaderMap, name: &str) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+// RFC 7239: `Forwarded: for=192.0.2.60;proto=http, for="[2001:db8::1]:4711"`.
+fn forwarded_elements(headers: &HeaderMap) -> Vec<String> {
+    header_list(headers, "forwarded")
+}
+
+fn param(element: &str, name: &str) -> Option<String> {
+    element.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Parses `ip`, `ip:port`, `[ipv6]` or `[ipv6]:port`. Obfuscated identifiers
+/// such as `unknown` or `_hidden` yield `None`.
+fn parse_node(node: &str) -> Option<SocketAddr> {
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(SocketAddr::new(ip, 0));
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr);
+    }
+    let ip = node.strip_prefix('[')?.strip_suffix(']')?.parse().ok()?;
+    Some(SocketAddr::new(ip, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxies(list: &[&str]) -> TrustedProxies {
+        let list: Vec<String> = list.iter().map(|proxy| proxy.to_string()).collect();
+        TrustedProxies::parse(&list).unwrap()
+    }
+
+    fn header_map(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn peer(addr: &str) -> Option<SocketAddr> {
+        Some(addr.parse().unwrap())
+    }
+
+    fn client_ip(client: &Client) -> Option<IpAddr> {
+        client.addr.map(|addr| addr.ip())
+    }
+
+    #[test]
+    fn network_boundaries() {
+        let net = Network::parse("10.1.0.0/16").unwrap();
+        assert!(net.contains("10.1.0.0".parse().unwrap()));
+        assert!(net.contains("10.1.255.255".parse().unwrap()));
+        assert!(!net.contains("10.0.255.255".parse().unwrap()));
+        assert!(!net.contains("10.2.0.0".parse().unwrap()));
+
+        let any = Network::parse("0.0.0.0/0").unwrap();
+        assert!(any.contains("203.0.113.9".parse().unwrap()));
+        assert!(!any.contains("::1".parse().unwrap()));
+
+        let host = Network::parse("192.0.2.1").unwrap();
+        assert!(host.contains("192.0.2.1".parse().unwrap()));
+        assert!(!host.contains("192.0.2.2".parse().unwrap()));
+
+        assert!(Network::parse("10.0.0.0/33").is_none());
+        assert!(Network::parse("::/129").is_none());
+        assert!(Network::parse("10.0.0.0/x").is_none());
+        assert!(Network::parse("not-an-ip").is_none());
+    }
+
+    #[test]
+    fn network_ipv6_prefixes() {
+        let net = Network::parse("2001:db8::/32").unwrap();
+        assert!(net.contains("2001:db8::1".parse().unwrap()));
+        assert!(net.contains("2001:db8:ffff:ffff::".parse().unwrap()));
+        assert!(!net.contains("2001:db9::".parse().unwrap()));
+        assert!(!net.contains("10.0.0.1".parse().unwrap()));
+
+        let host = Network::parse("::1").unwrap();
+        assert!(host.contains("::1".parse().unwrap()));
+        assert!(!host.contains("::2".parse().unwrap()));
+
+        // IPv4-mapped addresses from a dual-stack socket match IPv4 networks.
+        let v4 = Network::parse("127.0.0.0/8").unwrap();
+        assert!(v4.contains("::ffff:127.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn untrusted_peer_headers_are_ignored() {
+        let trusted = proxies(&["10.0.0.0/8"]);
+        let headers = header_map(&[
+            ("x-forwarded-for", "198.51.100.7"),
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "example.com"),
+        ]);
+        let client = trusted.resolve(peer("203.0.113.5:4000"), &headers);
+        assert_eq!(client.addr, peer("203.0.113.5:4000"));
+        assert_eq!(client.scheme, None);
+        assert_eq!(client.host, None);
+    }
+
+    #[test]
+    fn trusted_peer_headers_are_applied() {
+        let trusted = proxies(&["10.0.0.0/8"]);
+        let headers = header_map(&[
+            ("x-forwarded-for", "198.51.100.7"),
+            ("x-forwarded-proto", "HTTPS"),
+            ("x-forwarded-host", "example.com"),
+        ]);
+        let client = trusted.resolve(peer("10.0.0.2:4000"), &headers);
+        assert_eq!(client_ip(&client), Some("198.51.100.7".parse().unwrap()));
+        assert_eq!(client.scheme.as_deref(), Some("https"));
+        assert_eq!(client.host.as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn rightmost_untrusted_hop_is_the_client() {
+        let trusted = proxies(&["10.0.0.0/8"]);
+        // The leftmost entry is whatever the client claimed and must not win.
+        let headers = header_map(&[
+            ("x-forwarded-for", "1.1.1.1, 198.51.100.7"),
+            ("x-forwarded-for", "10.0.0.9"),
+        ]);
+        let client = trusted.resolve(peer("10.0.0.2:4000"), &headers);
+        assert_eq!(client_ip(&client), Some("198.51.100.7".parse().unwrap()));
+
+        // When every hop is trusted, the furthest one is the best guess.
+        let headers = header_map(&[("x-forwarded-for", "10.0.0.8, 10.0.0.9")]);
+        let client = trusted.resolve(peer("10.0.0.2:4000"), &headers);
+        assert_eq!(client_ip(&client), Some("10.0.0.8".parse().unwrap()));
+
+        // An obfuscated hop stops the walk at the last address that parsed.
+        let headers = header_map(&[("x-forwarded-for", "198.51.100.7, unknown, 10.0.0.9")]);
+        let client = trusted.resolve(peer("10.0.0.2:4000"), &headers);
+        assert_eq!(client_ip(&client), Some("10.0.0.9".parse().unwrap()));
+    }
+
+    #[test]
+    fn forwarded_header_takes_precedence() {
+        let trusted = proxies(&["127.0.0.1"]);
+        let headers = header_map(&[
+            (
+                "forwarded",
+                r#"for="[2001:db8::1]:4711";proto=https;host="example.com", for=127.0.0.1"#,
+            ),
+            ("x-forwarded-for", "198.51.100.7"),
+            ("x-forwarded-proto", "http"),
+        ]);
+        let client = trusted.resolve(peer("127.0.0.1:4000"), &headers);
+        assert_eq!(client.addr, peer("[2001:db8::1]:4711"));
+        assert_eq!(client.scheme.as_deref(), Some("https"));
+        assert_eq!(client.host.as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn forwarded_node_forms() {
+        assert_eq!(parse_node("192.0.2.60"), peer("192.0.2.60:0"));
+        assert_eq!(parse_node("192.0.2.60:8080"), peer("192.0.2.60:8080"));
+        assert_eq!(parse_node("[2001:db8::1]"), peer("[2001:db8::1]:0"));
+        assert_eq!(parse_node("2001:db8::1"), peer("[2001:db8::1]:0"));
+        assert_eq!(parse_node("unknown"), None);
+        assert_eq!(parse_node("_hidden"), None);
+    }
+
+    #[test]
+    fn unix_peers_need_explicit_trust() {
+        let headers = header_map(&[("x-forwarded-for", "198.51.100.7")]);
+        let client = proxies(&["127.0.0.1"]).resolve(None, &headers);
+        assert_eq!(client.addr, None);
+
+        let client = proxies(&["unix"]).resolve(None, &headers);
+        assert_eq!(client_ip(&client), Some("198.51.100.7".parse().unwrap()));
+    }
+}
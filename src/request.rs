@@ -1,44 +1,157 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, OnceLock},
+};
 
-use pyo3::{prelude::*, types::PyDict};
+use hyper::body::Bytes;
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+
+use crate::{
+    body::RequestBody,
+    headers::{HeaderItems, Headers},
+    multidict::MultiDict,
+    multipart::{self, FormData},
+};
+
+pub const URLENCODED: &str = "application/x-www-form-urlencoded";
 
 #[derive(Clone, Debug)]
 #[pyclass]
 pub struct Request {
     pub method: String,
     pub uri: String,
-    pub headers: HashMap<String, String>,
-    pub body: Option<String>,
+    pub headers: Headers,
+    pub body: Bytes,
+    /// A `multipart/form-data` body, parsed the first time it is needed.
+    form_data: Arc<OnceLock<Result<FormData, String>>>,
+    pub client_addr: Option<SocketAddr>,
+    pub scheme: String,
+    pub http_version: String,
+    pub host: Option<String>,
+    pub stream: Option<RequestBody>,
 }
 
 #[pymethods]
 impl Request {
     #[new]
-    pub fn new(method: String, uri: String, headers: HashMap<String, String>) -> Self {
-        Self {
-            method,
-            uri,
-            headers,
-            body: None,
-        }
+    pub fn new(method: String, uri: String, headers: HeaderItems) -> PyResult<Self> {
+        Ok(Self::from_parts(method, uri, headers.try_into()?))
     }
 
-    pub fn json(&self, py: Python<'_>) -> PyResult<Py<PyDict>> {
-        if let Some(ref body) = self.body {
-            crate::json::loads(body)
+    pub fn json(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        if self.body.is_empty() {
+            Ok(PyDict::new(py).into_any().unbind())
         } else {
-            Ok(PyDict::new(py).into())
+            crate::json::loads(&self.body)
+        }
+    }
+
+    /// The body decoded as text, or `None` when the request has no body.
+    #[getter]
+    fn body(&self, py: Python<'_>) -> PyResult<Option<String>> {
+        if self.body.is_empty() {
+            return Ok(None);
+        }
+        self.text(None, py).map(Some)
+    }
+
+    /// The body of a route declared with `stream=True`, still to be read.
+    #[getter]
+    fn stream(&self) -> Option<RequestBody> {
+        self.stream.clone()
+    }
+
+    /// The raw body bytes.
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.body)
+    }
+
+    /// Decodes the body with `encoding`, falling back to the charset declared in
+    /// `Content-Type` and then UTF-8. Undecodable bytes are replaced, and a
+    /// declared charset Python cannot decode with is treated as UTF-8.
+    #[pyo3(signature=(encoding=None))]
+    pub fn text(&self, encoding: Option<String>, py: Python<'_>) -> PyResult<String> {
+        let body = PyBytes::new(py, &self.body);
+        let decode = |encoding: &str| body.call_method1("decode", (encoding, "replace"));
+        let text = match (encoding, self.charset()) {
+            (Some(encoding), _) => decode(&encoding)?,
+            (None, Some(charset)) => decode(&charset).or_else(|_| decode("utf-8"))?,
+            (None, None) => decode("utf-8")?,
+        };
+        text.extract()
+    }
+
+    /// The fields of an `application/x-www-form-urlencoded` or
+    /// `multipart/form-data` body. Other bodies give an empty mapping.
+    pub fn form(&self, py: Python<'_>) -> PyResult<MultiDict> {
+        if let Some(form_data) = self.form_data()? {
+            return MultiDict::from_pairs(py, form_data.fields.clone());
+        }
+        if self
+            .mime()
+            .is_some_and(|mime| mime.eq_ignore_ascii_case(URLENCODED))
+        {
+            return MultiDict::from_pairs(py, parse_urlencoded(&self.body));
         }
+        MultiDict::from_pairs(py, Vec::<(String, String)>::new())
+    }
+
+    /// The uploaded files of a `multipart/form-data` body, keyed by field name.
+    pub fn files(&self, py: Python<'_>) -> PyResult<MultiDict> {
+        let files = self.form_data()?.into_iter().flat_map(|data| &data.files);
+        MultiDict::from_pairs(py, files.map(|file| (file.name.clone(), file.clone())))
+    }
+
+    #[getter]
+    fn headers(&self) -> Headers {
+        self.headers.share()
+    }
+
+    /// The cookies sent in the `Cookie` headers.
+    #[getter]
+    fn cookies(&self) -> HashMap<String, String> {
+        let headers = self.headers.lock();
+        let values = headers.get_all("cookie").iter();
+        crate::cookie::parse(values.filter_map(|value| value.to_str().ok()))
+    }
+
+    /// The client's `(ip, port)`, after trusted proxy headers are applied. The
+    /// port is `0` when a proxy did not forward it; `None` on Unix sockets.
+    #[getter]
+    fn client_addr(&self) -> Option<(String, u16)> {
+        self.client_addr
+            .map(|addr| (addr.ip().to_string(), addr.port()))
     }
 
+    /// `"http"` or `"https"`, as seen by the client.
     #[getter]
-    fn body(&self) -> Option<String> {
-        self.body.clone()
+    fn scheme(&self) -> String {
+        self.scheme.clone()
     }
 
+    /// `"HTTP/1.0"`, `"HTTP/1.1"` or `"HTTP/2"`.
     #[getter]
-    fn headers(&self) -> HashMap<String, String> {
-        self.headers.clone()
+    fn http_version(&self) -> String {
+        self.http_version.clone()
+    }
+
+    /// The requested host, from `Host` or the HTTP/2 `:authority`.
+    #[getter]
+    fn host(&self) -> Option<String> {
+        self.host.clone()
+    }
+
+    /// The `Last-Event-ID` an `EventSource` sends when it reconnects, so an
+    /// event stream can resume where it left off.
+    #[getter]
+    fn last_event_id(&self) -> Option<String> {
+        self.headers.get_str("last-event-id")
     }
 
     #[getter]
@@ -51,13 +164,9 @@ impl Request {
         self.method.clone()
     }
 
-    fn query(&self) -> PyResult<Option<HashMap<String, String>>> {
-        let query_string = self.uri.split('?').nth(1);
-        if let Some(query) = query_string {
-            let query_params = Self::parse_query_string(query.to_string());
-            return Ok(Some(query_params));
-        }
-        Ok(None)
+    /// The decoded query string parameters.
+    fn query(&self, py: Python<'_>) -> PyResult<MultiDict> {
+        MultiDict::from_pairs(py, self.query_pairs())
     }
 
     pub fn __repr__(&self) -> String {
@@ -66,19 +175,77 @@ impl Request {
 }
 
 impl Request {
-    fn parse_query_string(query_string: String) -> HashMap<String, String> {
-        query_string
-            .split('&')
-            .filter_map(|pair| {
-                let mut parts = pair.split('=');
-                let key = parts.next()?.to_string();
-                let value = parts.next().map_or("".to_string(), |v| v.to_string());
-                Some((key, value))
-            })
-            .collect()
+    pub fn from_parts(method: String, uri: String, headers: Headers) -> Self {
+        Self {
+            method,
+            uri,
+            headers,
+            body: Bytes::new(),
+            form_data: Arc::default(),
+            client_addr: None,
+            scheme: "http".to_string(),
+            http_version: "HTTP/1.1".to_string(),
+            host: None,
+            stream: None,
+        }
+    }
+
+    pub fn query_pairs(&self) -> Vec<(String, String)> {
+        let query = self.uri.split_once('?').map_or("", |(_, query)| query);
+        parse_urlencoded(query.as_bytes())
+    }
+
+    pub fn set_body(&mut self, body: Bytes) {
+        self.body = body;
     }
 
-    pub fn set_body(&mut self, body: String) {
-        self.body = Some(body);
+    pub fn set_stream(&mut self, stream: RequestBody) {
+        self.stream = Some(stream);
     }
+
+    /// The parsed `multipart/form-data` body, or `None` for other bodies. A
+    /// malformed body raises `ValueError`.
+    fn form_data(&self) -> PyResult<Option<&FormData>> {
+        let content_type = self.headers.get_str("content-type");
+        let Some(content_type) = content_type.filter(|value| multipart::is_multipart(value)) else {
+            return Ok(None);
+        };
+        if self.stream.is_some() {
+            return Ok(None);
+        }
+        let form_data = self.form_data.get_or_init(|| {
+            multipart::parse(&content_type, self.body.clone()).map_err(|err| err.to_string())
+        });
+        match form_data {
+            Ok(form_data) => Ok(Some(form_data)),
+            Err(err) => Err(PyValueError::new_err(format!(
+                "invalid multipart body: {err}"
+            ))),
+        }
+    }
+
+    /// The media type of the body, without parameters.
+    pub fn mime(&self) -> Option<String> {
+        let content_type = self.headers.get_str("content-type")?;
+        content_type
+            .split(';')
+            .next()
+            .map(|mime| mime.trim().to_string())
+    }
+
+    fn charset(&self) -> Option<String> {
+        let content_type = self.headers.get_str("content-type")?;
+        content_type.split(';').skip(1).find_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            key.trim()
+                .eq_ignore_ascii_case("charset")
+                .then(|| value.trim().trim_matches('"').to_string())
+        })
+    }
+}
+
+/// Decodes `application/x-www-form-urlencoded` data: `+` becomes a space,
+/// percent escapes are decoded and repeated keys are all kept.
+fn parse_urlencoded(input: &[u8]) -> Vec<(String, String)> {
+    form_urlencoded::parse(input).into_owned().collect()
 }
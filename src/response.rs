@@ -1,16 +1,25 @@
-use std::collections::HashMap;
-
 use hyper::body::Bytes;
 use pyo3::{prelude::*, types::PyBytes};
 
-use crate::{into_response::IntoResponse, status::Status};
+use crate::{
+    cookie::{self, Cookie},
+    file_response::FileSource,
+    headers::{HeaderItems, Headers},
+    into_response::IntoResponse,
+    status::Status,
+    streaming::ChunkSource,
+};
 
 #[derive(Clone)]
-#[pyclass]
+#[pyclass(subclass)]
 pub struct Response {
     pub status: Status,
     pub body: Bytes,
-    pub headers: HashMap<String, String>,
+    pub headers: Headers,
+    /// Set by `StreamingResponse`: the body is pulled from this instead of `body`.
+    pub stream: Option<ChunkSource>,
+    /// Set by `FileResponse`: the body is read from disk.
+    pub file: Option<FileSource>,
 }
 
 #[pymethods]
@@ -34,12 +43,80 @@ impl Response {
         Ok(Self {
             status: status.clone(),
             body,
-            headers: HashMap::from([("Content-Type".to_string(), content_type)]),
+            headers: Headers::with_content_type(&content_type)?,
+            stream: None,
+            file: None,
         })
     }
 
-    pub fn header(&mut self, key: String, value: String) {
-        self.headers.insert(key, value);
+    /// Sets `key` to `value`, replacing any previous value.
+    pub fn header(&self, key: &str, value: &str) -> PyResult<()> {
+        self.headers.insert(key, value)
+    }
+
+    /// Adds a `Set-Cookie` header; every cookie set gets its own header.
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature=(
+        name,
+        value,
+        max_age=None,
+        expires=None,
+        path="/",
+        domain=None,
+        secure=false,
+        httponly=false,
+        samesite=None,
+    ))]
+    fn set_cookie(
+        &self,
+        name: &str,
+        value: &str,
+        max_age: Option<i64>,
+        expires: Option<&Bound<'_, PyAny>>,
+        path: Option<&str>,
+        domain: Option<&str>,
+        secure: bool,
+        httponly: bool,
+        samesite: Option<&str>,
+    ) -> PyResult<()> {
+        let cookie = Cookie {
+            name,
+            value,
+            max_age,
+            expires: expires.map(cookie::expires).transpose()?,
+            path,
+            domain,
+            secure,
+            httponly,
+            samesite,
+        };
+        self.headers.append("set-cookie", &cookie.to_header()?)
+    }
+
+    /// Tells the client to drop a cookie. `path` and `domain` must match the
+    /// ones it was set with.
+    #[pyo3(signature=(name, path="/", domain=None))]
+    fn delete_cookie(&self, name: &str, path: Option<&str>, domain: Option<&str>) -> PyResult<()> {
+        let cookie = Cookie {
+            name,
+            max_age: Some(0),
+            expires: Some(cookie::epoch()),
+            path,
+            domain,
+            ..Default::default()
+        };
+        self.headers.append("set-cookie", &cookie.to_header()?)
+    }
+
+    #[getter]
+    fn headers(&self) -> Headers {
+        self.headers.share()
+    }
+
+    #[setter]
+    fn set_headers(&mut self, headers: HeaderItems) -> PyResult<()> {
+        self.headers = headers.try_into()?;
+        Ok(())
     }
 }
 
@@ -1,8 +1,11 @@
-use std::{collections::HashMap, mem::transmute, sync::Arc};
+use std::{collections::HashMap, sync::Arc};
 
 use pyo3::{exceptions::PyException, ffi::c_str, prelude::*, pyclass, types::PyDict, Py, PyAny};
 
-use crate::{middleware::Middleware, to_py_exception};
+use crate::{middleware::Middleware, params, to_py_exception};
+
+/// Path parameters of a matched route, as `(name, value)` pairs.
+pub type Params = Vec<(String, String)>;
 
 #[derive(Clone, Debug)]
 #[pyclass]
@@ -11,27 +14,45 @@ pub struct Route {
     pub path: String,
     pub handler: Arc<Py<PyAny>>,
     pub args: Arc<Vec<String>>,
+    pub annotations: Arc<HashMap<String, Py<PyAny>>>,
     pub content_type: String,
     pub data: Option<String>,
+    pub form: Option<String>,
+    pub files: Option<String>,
+    pub query: Arc<Vec<String>>,
+    pub max_body_size: Option<usize>,
+    pub stream: bool,
 }
 
 #[pymethods]
 impl Route {
     #[new]
-    #[pyo3(signature=(path, method=None, content_type=None, data=None))]
+    #[pyo3(signature=(path, method=None, content_type=None, data=None, max_body_size=None, form=None, files=None, stream=false, query=None))]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         path: String,
         method: Option<String>,
         content_type: Option<String>,
         data: Option<String>,
+        max_body_size: Option<usize>,
+        form: Option<String>,
+        files: Option<String>,
+        stream: bool,
+        query: Option<Vec<String>>,
     ) -> Self {
         Route {
             method: method.unwrap_or_else(|| "GET".to_string()),
             path,
             handler: Arc::new(Python::with_gil(|py| py.None())),
             args: Arc::new(Vec::new()),
+            annotations: Arc::new(HashMap::new()),
             content_type: content_type.unwrap_or_else(|| "application/json".to_string()),
             data,
+            form,
+            files,
+            query: Arc::new(query.unwrap_or_default()),
+            max_body_size,
+            stream,
         }
     }
 
@@ -49,16 +70,20 @@ impl Route {
             args.push(name);
         }
 
-        if let Some(data) = self.data.clone() {
-            if !args.contains(&data) {
-                let message = format!("Missing argument '{data}'");
+        let named = [&self.data, &self.form, &self.files].into_iter().flatten();
+        for name in named.chain(self.query.iter()) {
+            if !args.contains(name) {
+                let message = format!("Missing argument '{name}'");
                 return Err(PyException::new_err(message));
             }
         }
 
+        let annotations = params::annotations(handler.bind(py))?.extract()?;
+
         Ok(Self {
             handler: Arc::new(handler),
             args: Arc::new(args),
+            annotations: Arc::new(annotations),
             ..self.clone()
         })
     }
@@ -72,13 +97,28 @@ macro_rules! method_decorator {
     ($($method:ident),*) => {
         $(
             #[pyfunction]
-            #[pyo3(signature = (path, *, content_type=None, data=None))]
-            pub fn $method(path: String, content_type: Option<String>, data: Option<String>)-> Route{
+            #[pyo3(signature = (path, *, content_type=None, data=None, max_body_size=None, form=None, files=None, stream=false, query=None))]
+            #[allow(clippy::too_many_arguments)]
+            pub fn $method(
+                path: String,
+                content_type: Option<String>,
+                data: Option<String>,
+                max_body_size: Option<usize>,
+                form: Option<String>,
+                files: Option<String>,
+                stream: bool,
+                query: Option<Vec<String>>,
+            ) -> Route {
                 Route::new(
                     path,
                     Some(stringify!($method).to_string().to_uppercase()),
                     content_type,
                     data,
+                    max_body_size,
+                    form,
+                    files,
+                    stream,
+                    query,
                 )
             }
         )+
@@ -90,7 +130,7 @@ method_decorator!(get, post, put, patch, delete);
 #[derive(Default, Clone, Debug)]
 #[pyclass]
 pub struct Router {
-    pub routes: HashMap<String, matchit::Router<Route>>,
+    pub routes: HashMap<String, matchit::Router<Arc<Route>>>,
     pub middlewares: Vec<Middleware>,
 }
 
@@ -101,14 +141,15 @@ impl Router {
         Router::default()
     }
 
-    fn middleware(&mut self, middleware: Py<PyAny>) {
-        let middleware = Middleware::new(middleware);
+    fn middleware(&mut self, middleware: Py<PyAny>, py: Python<'_>) -> PyResult<()> {
+        let middleware = Middleware::new(middleware, py)?;
         self.middlewares.push(middleware);
+        Ok(())
     }
 
     fn route(&mut self, route: PyRef<Route>) -> PyResult<()> {
         let method_router = self.routes.entry(route.method.clone()).or_default();
-        to_py_exception(method_router.insert(&route.path, route.clone()))?;
+        to_py_exception(method_router.insert(&route.path, Arc::new(route.clone())))?;
         Ok(())
     }
 
@@ -121,15 +162,17 @@ impl Router {
 }
 
 impl Router {
-    pub fn find<'l>(&self, method: &str, uri: &str) -> Option<matchit::Match<'l, 'l, &'l Route>> {
+    /// Finds the route for `method` and `uri` along with the path parameters,
+    /// copied out so they can outlive the request URI.
+    pub fn find(&self, method: &str, uri: &str) -> Option<(Arc<Route>, Params)> {
         let path = uri.split('?').next().unwrap_or(uri);
-        if let Some(router) = self.routes.get(method) {
-            if let Ok(route) = router.at(path) {
-                let route: matchit::Match<'l, 'l, &Route> = unsafe { transmute(route) };
-                return Some(route);
-            }
-        }
-        None
+        let route = self.routes.get(method)?.at(path).ok()?;
+        let params = route
+            .params
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        Some((route.value.clone(), params))
     }
 }
 
@@ -137,25 +180,23 @@ impl Router {
 pub fn static_file(directory: String, path: String, py: Python<'_>) -> PyResult<Route> {
     let pathlib = py.import("pathlib")?;
     let oxapy = py.import("oxapy")?;
-    let mimetypes = py.import("mimetypes")?;
 
     let globals = &PyDict::new(py);
+    // `FileResponse` imports `mimetypes`, which looks up `__import__` here.
+    globals.set_item("__builtins__", py.import("builtins")?)?;
     globals.set_item("Path", pathlib.getattr("Path")?)?;
     globals.set_item("directory", directory)?;
     globals.set_item("Status", oxapy.getattr("Status")?)?;
     globals.set_item("Response", oxapy.getattr("Response")?)?;
-    globals.set_item("mimetypes", mimetypes)?;
+    globals.set_item("FileResponse", oxapy.getattr("FileResponse")?)?;
 
     py.run(
         c_str!(
             r#"
 def static_file(path):
-    file_path = f"{directory}/{path}"
     try:
-        with open(file_path, "rb") as f: content = f.read()
-        content_type, _ = mimetypes.guess_type(file_path)
-        return Response(Status.OK, content, content_type or "application/octet-stream")
-    except FileNotFoundError:
+        return FileResponse(f"{directory}/{path}")
+    except (FileNotFoundError, IsADirectoryError, NotADirectoryError):
         return Response(Status.NOT_FOUND, "File not found")
 "#
         ),
@@ -168,6 +209,11 @@ def static_file(path):
         Some("GET".to_string()),
         Some("text/plain".to_string()),
         None,
+        None,
+        None,
+        None,
+        false,
+        None,
     );
 
     let handler = globals.get_item("static_file")?.unwrap();
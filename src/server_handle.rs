@@ -0,0 +1,68 @@
+use std::{sync::Mutex, thread::JoinHandle, time::Duration};
+
+use pyo3::prelude::*;
+use tokio::sync::oneshot;
+
+use crate::{listener::Address, seconds};
+
+/// Returned by `HttpServer.start()` to control a server running on a background thread.
+#[pyclass]
+pub struct ServerHandle {
+    addresses: Vec<Address>,
+    stop_tx: Mutex<Option<oneshot::Sender<Option<Duration>>>>,
+    thread: Mutex<Option<JoinHandle<PyResult<()>>>>,
+}
+
+impl ServerHandle {
+    pub fn new(
+        addresses: Vec<Address>,
+        stop_tx: oneshot::Sender<Option<Duration>>,
+        thread: JoinHandle<PyResult<()>>,
+    ) -> Self {
+        Self {
+            addresses,
+            stop_tx: Mutex::new(Some(stop_tx)),
+            thread: Mutex::new(Some(thread)),
+        }
+    }
+}
+
+#[pymethods]
+impl ServerHandle {
+    /// The first bound address: `(ip, port)` for TCP or the socket path for Unix.
+    #[getter]
+    fn address(&self) -> Address {
+        self.addresses[0].clone()
+    }
+
+    #[getter]
+    fn addresses(&self) -> Vec<Address> {
+        self.addresses.clone()
+    }
+
+    #[pyo3(signature=(timeout=None))]
+    fn stop(&self, timeout: Option<f64>, py: Python<'_>) -> PyResult<()> {
+        let timeout = seconds(timeout)?;
+        if let Some(stop_tx) = self.stop_tx.lock().unwrap().take() {
+            _ = stop_tx.send(timeout);
+        }
+        self.join(py)
+    }
+
+    fn join(&self, py: Python<'_>) -> PyResult<()> {
+        let thread = self.thread.lock().unwrap().take();
+        match thread {
+            Some(thread) => match py.allow_threads(|| thread.join()) {
+                Ok(result) => result,
+                Err(_) => Err(pyo3::exceptions::PyRuntimeError::new_err(
+                    "Server thread panicked",
+                )),
+            },
+            None => Ok(()),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("ServerHandle(address={})", self.addresses[0])
+    }
+}
@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+use hyper::body::Bytes;
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyBytes, PyDict, PyString},
+};
+
+use crate::{
+    headers::Headers,
+    response::Response,
+    status::Status,
+    streaming::{ChunkSource, StreamingResponse},
+};
+
+const KEEP_ALIVE: &[u8] = b": keep-alive\n\n";
+
+/// One server-sent event. `data` that is not a string is sent as JSON.
+#[derive(Debug, Default)]
+#[pyclass]
+pub struct Event {
+    #[pyo3(get, set)]
+    data: Option<Py<PyAny>>,
+    #[pyo3(get, set)]
+    event: Option<String>,
+    #[pyo3(get, set)]
+    id: Option<String>,
+    #[pyo3(get, set)]
+    retry: Option<u64>,
+    #[pyo3(get, set)]
+    comment: Option<String>,
+}
+
+#[pymethods]
+impl Event {
+    #[new]
+    #[pyo3(signature=(data=None, event=None, id=None, retry=None, comment=None))]
+    fn new(
+        data: Option<Py<PyAny>>,
+        event: Option<String>,
+        id: Option<String>,
+        retry: Option<u64>,
+        comment: Option<String>,
+    ) -> Self {
+        Self {
+            data,
+            event,
+            id,
+            retry,
+            comment,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Event(event={:?}, id={:?})", self.event, self.id)
+    }
+}
+
+impl Event {
+    fn from_dict(fields: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let field = |name: &str| fields.get_item(name);
+        Ok(Self {
+            data: field("data")?.map(Bound::unbind),
+            event: field("event")?.map(|v| v.extract()).transpose()?,
+            id: field("id")?
+                .map(|v| v.str().map(|v| v.to_string()))
+                .transpose()?,
+            retry: field("retry")?.map(|v| v.extract()).transpose()?,
+            comment: field("comment")?.map(|v| v.extract()).transpose()?,
+        })
+    }
+
+    /// Renders the event in the `text/event-stream` format.
+    fn encode(&self, py: Python<'_>) -> PyResult<String> {
+        let mut out = String::new();
+        if let Some(comment) = &self.comment {
+            for line in lines(comment) {
+                out.push_str(&format!(": {line}\n"));
+            }
+        }
+        if let Some(event) = &self.event {
+            out.push_str(&format!("event: {}\n", single_line("event", event)?));
+        }
+        if let Some(id) = &self.id {
+            if id.contains('\0') {
+                return Err(PyValueError::new_err("event id must not contain NUL"));
+            }
+            out.push_str(&format!("id: {}\n", single_line("id", id)?));
+        }
+        if let Some(retry) = self.retry {
+            out.push_str(&format!("retry: {retry}\n"));
+        }
+        if let Some(data) = &self.data {
+            let data = data.bind(py);
+            let data = if let Ok(text) = data.downcast::<PyString>() {
+                text.to_string()
+            } else if let Ok(bytes) = data.downcast::<PyBytes>() {
+                String::from_utf8_lossy(bytes.as_bytes()).into_owned()
+            } else {
+                crate::json::dumps(&data.clone().unbind())?
+            };
+            // Every line of the payload needs its own `data:` field.
+            for line in lines(&data) {
+                out.push_str(&format!("data: {line}\n"));
+            }
+        }
+        out.push('\n');
+        Ok(out)
+    }
+}
+
+/// Splits on every line break the `text/event-stream` format recognises, so a
+/// lone `\r` cannot start a field of its own.
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.split("\r\n").flat_map(|line| line.split(['\r', '\n']))
+}
+
+fn single_line<'a>(field: &str, value: &'a str) -> PyResult<&'a str> {
+    match value.contains(['\r', '\n']) {
+        true => Err(PyValueError::new_err(format!(
+            "SSE {field} must be a single line"
+        ))),
+        false => Ok(value),
+    }
+}
+
+fn encode(item: &Bound<'_, PyAny>) -> PyResult<Bytes> {
+    let py = item.py();
+    let event = if let Ok(event) = item.downcast::<Event>() {
+        event.borrow().encode(py)?
+    } else if let Ok(fields) = item.downcast::<PyDict>() {
+        Event::from_dict(fields)?.encode(py)?
+    } else {
+        let event = Event {
+            data: Some(item.clone().unbind()),
+            ..Default::default()
+        };
+        event.encode(py)?
+    };
+    Ok(event.into())
+}
+
+/// A `text/event-stream` response fed by an iterator or async iterator of
+/// events: `Event`s, dicts with the same fields, or bare data.
+#[pyclass(extends=StreamingResponse)]
+pub struct EventStream;
+
+#[pymethods]
+impl EventStream {
+    #[new]
+    #[pyo3(signature=(events, keep_alive=Some(15.0), retry=None, status=None))]
+    fn new(
+        events: &Bound<'_, PyAny>,
+        keep_alive: Option<f64>,
+        retry: Option<u64>,
+        status: Option<PyRef<'_, Status>>,
+    ) -> PyResult<PyClassInitializer<Self>> {
+        let mut stream = ChunkSource::new(events)?.encode_with(encode);
+        if let Some(keep_alive) = keep_alive {
+            let interval = crate::to_py_exception(Duration::try_from_secs_f64(keep_alive))?;
+            stream = stream.keep_alive(interval, KEEP_ALIVE);
+        }
+        if let Some(retry) = retry {
+            stream = stream.prelude(format!("retry: {retry}\n\n").into());
+        }
+
+        let headers = Headers::with_content_type("text/event-stream")?;
+        headers.insert("cache-control", "no-cache")?;
+        // Keeps nginx from buffering the stream.
+        headers.insert("x-accel-buffering", "no")?;
+        let response = Response {
+            status: status.map_or(Status::OK, |status| status.clone()),
+            body: Bytes::new(),
+            headers,
+            stream: Some(stream),
+            file: None,
+        };
+        Ok(PyClassInitializer::from(response)
+            .add_subclass(StreamingResponse)
+            .add_subclass(Self))
+    }
+}
@@ -1,9 +1,7 @@
-use std::collections::HashMap;
-
 use hyper::body::Bytes;
 use pyo3::prelude::*;
 
-use crate::{into_response::IntoResponse, response::Response};
+use crate::{headers::Headers, into_response::IntoResponse, response::Response};
 
 #[derive(Clone)]
 #[pyclass]
@@ -30,8 +28,10 @@ impl IntoResponse for Status {
     fn into_response(&self) -> PyResult<Response> {
         Ok(Response {
             status: self.clone(),
-            headers: HashMap::from([("Content-Type".to_string(), "text/plain".to_string())]),
+            headers: Headers::with_content_type("text/plain")?,
             body: Bytes::new(),
+            stream: None,
+            file: None,
         })
     }
 }
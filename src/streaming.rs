@@ -0,0 +1,269 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use pyo3::{
+    exceptions::{PyStopAsyncIteration, PyStopIteration, PyTypeError},
+    prelude::*,
+    types::{PyByteArray, PyBytes, PyString},
+};
+use tokio::{sync::mpsc, time::sleep};
+
+use crate::{coroutine, headers::Headers, response::Response, status::Status};
+
+/// The body of every response the server sends: either fully in memory or
+/// produced chunk by chunk by a Python iterator.
+pub enum ResponseBody {
+    Full(Bytes),
+    Stream(mpsc::Receiver<io::Result<Bytes>>),
+}
+
+impl Body for ResponseBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, io::Error>>> {
+        match self.get_mut() {
+            ResponseBody::Full(body) => match body.is_empty() {
+                true => Poll::Ready(None),
+                false => Poll::Ready(Some(Ok(Frame::data(std::mem::take(body))))),
+            },
+            ResponseBody::Stream(receiver) => receiver
+                .poll_recv(cx)
+                .map(|chunk| chunk.map(|chunk| chunk.map(Frame::data))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
+            ResponseBody::Full(body) => body.is_empty(),
+            ResponseBody::Stream(_) => false,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            ResponseBody::Full(body) => SizeHint::with_exact(body.len() as u64),
+            ResponseBody::Stream(_) => SizeHint::default(),
+        }
+    }
+}
+
+/// A Python iterator or async iterator whose items make up a response body.
+#[derive(Clone, Debug)]
+pub struct ChunkSource {
+    iterator: Arc<Py<PyAny>>,
+    is_async: bool,
+    /// Turns an item into the bytes sent for it.
+    encode: fn(&Bound<'_, PyAny>) -> PyResult<Bytes>,
+    /// Sent before the first item.
+    prelude: Option<Bytes>,
+    /// Sent whenever the iterator produces nothing for `keep_alive`.
+    keep_alive: Option<(Duration, Bytes)>,
+    /// The asyncio task computing the current item of an async iterator.
+    running: Arc<Mutex<Option<Py<PyAny>>>>,
+}
+
+impl ChunkSource {
+    pub fn new(content: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let is_async = content.hasattr("__aiter__")?;
+        let iterator = match is_async {
+            true => content.call_method0("__aiter__")?,
+            false => content.try_iter()?.into_any(),
+        };
+        Ok(Self {
+            iterator: Arc::new(iterator.unbind()),
+            is_async,
+            encode: to_bytes,
+            prelude: None,
+            keep_alive: None,
+            running: Arc::default(),
+        })
+    }
+
+    pub fn encode_with(mut self, encode: fn(&Bound<'_, PyAny>) -> PyResult<Bytes>) -> Self {
+        self.encode = encode;
+        self
+    }
+
+    pub fn prelude(mut self, prelude: Bytes) -> Self {
+        self.prelude = Some(prelude);
+        self
+    }
+
+    pub fn keep_alive(mut self, interval: Duration, chunk: &'static [u8]) -> Self {
+        self.keep_alive = Some((interval, Bytes::from_static(chunk)));
+        self
+    }
+
+    /// Starts pulling chunks on the runtime. The channel holds a single chunk,
+    /// so the iterator only advances as fast as the client reads.
+    pub fn spawn(self) -> ResponseBody {
+        let (sender, receiver) = mpsc::channel(1);
+        tokio::spawn(self.produce(sender));
+        ResponseBody::Stream(receiver)
+    }
+
+    async fn produce(self, sender: mpsc::Sender<io::Result<Bytes>>) {
+        if let Some(prelude) = self.prelude.clone() {
+            if sender.send(Ok(prelude)).await.is_err() {
+                return self.close().await;
+            }
+        }
+        loop {
+            let next = self.next_chunk();
+            tokio::pin!(next);
+            let chunk = loop {
+                let keep_alive = self.keep_alive.as_ref();
+                let idle = sleep(keep_alive.map_or(Duration::MAX, |(interval, _)| *interval));
+                tokio::select! {
+                    chunk = &mut next => break chunk,
+                    _ = sender.closed() => {
+                        // The client went away: interrupt a pending async item,
+                        // then let the producer run its cleanup.
+                        self.cancel();
+                        _ = next.await;
+                        return self.close().await;
+                    }
+                    _ = idle, if keep_alive.is_some() => {
+                        let (_, comment) = keep_alive.unwrap();
+                        _ = sender.send(Ok(comment.clone())).await;
+                    }
+                }
+            };
+            let chunk = match chunk {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => return,
+                Err(err) => {
+                    Python::with_gil(|py| err.print(py));
+                    _ = sender.send(Err(io::Error::other(err.to_string()))).await;
+                    return;
+                }
+            };
+            if sender.send(Ok(chunk)).await.is_err() {
+                return self.close().await;
+            }
+        }
+    }
+
+    async fn next_chunk(&self) -> PyResult<Option<Bytes>> {
+        let iterator = self.iterator.clone();
+        let encode = self.encode;
+        if !self.is_async {
+            return blocking(move || {
+                Python::with_gil(|py| match iterator.bind(py).call_method0("__next__") {
+                    Ok(chunk) => encode(&chunk).map(Some),
+                    Err(err) if err.is_instance_of::<PyStopIteration>(py) => Ok(None),
+                    Err(err) => Err(err),
+                })
+            })
+            .await;
+        }
+
+        let running = self.running.clone();
+        let pending = blocking(move || {
+            Python::with_gil(|py| {
+                let next = iterator.bind(py).call_method0("__anext__")?;
+                let (pending, future) = coroutine::spawn_cancellable(&next)?;
+                *running.lock().unwrap() = Some(future);
+                Ok(pending)
+            })
+        })
+        .await?;
+        let result = pending.await;
+        self.running.lock().unwrap().take();
+        let result = result.unwrap_or_else(|_| Ok(Python::with_gil(|py| py.None())));
+        blocking(move || {
+            Python::with_gil(|py| match result {
+                Ok(chunk) => encode(chunk.bind(py)).map(Some),
+                Err(err) if err.is_instance_of::<PyStopAsyncIteration>(py) => Ok(None),
+                Err(err) => Err(err),
+            })
+        })
+        .await
+    }
+
+    fn cancel(&self) {
+        if let Some(future) = self.running.lock().unwrap().take() {
+            Python::with_gil(|py| _ = future.call_method0(py, "cancel"));
+        }
+    }
+
+    async fn close(&self) {
+        let iterator = self.iterator.clone();
+        let is_async = self.is_async;
+        let closing = blocking(move || {
+            Python::with_gil(|py| -> PyResult<Option<coroutine::Pending>> {
+                let iterator = iterator.bind(py);
+                match is_async {
+                    true if iterator.hasattr("aclose")? => {
+                        Ok(Some(coroutine::spawn(&iterator.call_method0("aclose")?)?))
+                    }
+                    false if iterator.hasattr("close")? => {
+                        iterator.call_method0("close")?;
+                        Ok(None)
+                    }
+                    _ => Ok(None),
+                }
+            })
+        })
+        .await;
+        if let Ok(Some(pending)) = closing {
+            _ = pending.await;
+        }
+    }
+}
+
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> PyResult<T> + Send + 'static,
+) -> PyResult<T> {
+    crate::to_py_exception(tokio::task::spawn_blocking(f).await)?
+}
+
+pub fn to_bytes(chunk: &Bound<'_, PyAny>) -> PyResult<Bytes> {
+    if let Ok(bytes) = chunk.downcast::<PyBytes>() {
+        Ok(Bytes::copy_from_slice(bytes.as_bytes()))
+    } else if let Ok(text) = chunk.downcast::<PyString>() {
+        Ok(Bytes::copy_from_slice(text.to_str()?.as_bytes()))
+    } else if let Ok(array) = chunk.downcast::<PyByteArray>() {
+        Ok(Bytes::from(array.to_vec()))
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "streamed chunks must be bytes or str, not {}",
+            chunk.get_type().name()?
+        )))
+    }
+}
+
+/// A response whose body is produced by an iterator or async iterator of
+/// `bytes`/`str` chunks and sent with chunked transfer encoding.
+#[pyclass(extends=Response, subclass)]
+pub struct StreamingResponse;
+
+#[pymethods]
+impl StreamingResponse {
+    #[new]
+    #[pyo3(signature=(content, content_type="application/octet-stream".to_string(), status=None))]
+    fn new(
+        content: &Bound<'_, PyAny>,
+        content_type: String,
+        status: Option<PyRef<'_, Status>>,
+    ) -> PyResult<(Self, Response)> {
+        let response = Response {
+            status: status.map_or(Status::OK, |status| status.clone()),
+            body: Bytes::new(),
+            headers: Headers::with_content_type(&content_type)?,
+            stream: Some(ChunkSource::new(content)?),
+            file: None,
+        };
+        Ok((Self, response))
+    }
+}
@@ -0,0 +1,126 @@
+use std::{path::PathBuf, sync::Arc};
+
+use pyo3::prelude::*;
+use tokio_rustls::{
+    rustls::{crypto::ring, ServerConfig},
+    TlsAcceptor,
+};
+
+use crate::to_py_exception;
+
+/// A certificate or key given either as a path to a PEM file or as raw PEM bytes.
+#[derive(FromPyObject)]
+pub enum PemSource {
+    Bytes(Vec<u8>),
+    Path(PathBuf),
+}
+
+impl PemSource {
+    fn read(self) -> PyResult<Vec<u8>> {
+        match self {
+            PemSource::Bytes(bytes) => Ok(bytes),
+            PemSource::Path(path) => Ok(std::fs::read(path)?),
+        }
+    }
+}
+
+pub fn build_acceptor(cert: PemSource, key: PemSource) -> PyResult<TlsAcceptor> {
+    let cert = cert.read()?;
+    let key = key.read()?;
+
+    let certs = to_py_exception(rustls_pemfile::certs(&mut cert.as_slice()).collect())?;
+    let key = to_py_exception(rustls_pemfile::private_key(&mut key.as_slice()))?
+        .ok_or_else(|| pyo3::exceptions::PyValueError::new_err("No private key found"))?;
+
+    let builder = to_py_exception(
+        ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions(),
+    )?;
+    let mut config = to_py_exception(builder.with_no_client_auth().with_single_cert(certs, key))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+    use tokio_rustls::{
+        rustls::{pki_types::ServerName, ClientConfig, RootCertStore},
+        TlsConnector,
+    };
+
+    /// Connects to a server using a fresh self-signed certificate and returns
+    /// the protocol ALPN settled on, after a round trip over the connection.
+    async fn negotiate(client_protocols: &[&[u8]]) -> Option<Vec<u8>> {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let acceptor = build_acceptor(
+            PemSource::Bytes(certified.cert.pem().into_bytes()),
+            PemSource::Bytes(certified.key_pair.serialize_pem().into_bytes()),
+        )
+        .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = acceptor.accept(stream).await.unwrap();
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).await.unwrap();
+            stream.write_all(&buf).await.unwrap();
+            stream.shutdown().await.unwrap();
+        });
+
+        let mut roots = RootCertStore::empty();
+        roots.add(certified.cert.der().clone()).unwrap();
+        let mut config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        config.alpn_protocols = client_protocols.iter().map(|p| p.to_vec()).collect();
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let name = ServerName::try_from("localhost").unwrap();
+        let mut stream = TlsConnector::from(Arc::new(config))
+            .connect(name, stream)
+            .await
+            .unwrap();
+        stream.write_all(b"ping").await.unwrap();
+        let mut echoed = Vec::new();
+        stream.read_to_end(&mut echoed).await.unwrap();
+        assert_eq!(echoed, b"ping");
+        server.await.unwrap();
+
+        stream.get_ref().1.alpn_protocol().map(<[u8]>::to_vec)
+    }
+
+    #[tokio::test]
+    async fn alpn_prefers_h2() {
+        let protocol = negotiate(&[b"http/1.1", b"h2"]).await;
+        assert_eq!(protocol.as_deref(), Some(&b"h2"[..]));
+    }
+
+    #[tokio::test]
+    async fn alpn_falls_back_to_http1() {
+        let protocol = negotiate(&[b"http/1.1"]).await;
+        assert_eq!(protocol.as_deref(), Some(&b"http/1.1"[..]));
+    }
+
+    #[tokio::test]
+    async fn alpn_is_optional() {
+        assert_eq!(negotiate(&[]).await, None);
+    }
+
+    #[test]
+    fn missing_key_is_rejected() {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let cert = PemSource::Bytes(certified.cert.pem().into_bytes());
+        assert!(build_acceptor(cert, PemSource::Bytes(Vec::new())).is_err());
+    }
+}